@@ -1,12 +1,8 @@
 use bevy::prelude::*;
 use bevy::sprite::MaterialMesh2dBundle;
 use clap::Parser;
-use stuff::ball::sweep_and_prune_collision_system_with_cache;
 #[allow(unused_imports)]
-use stuff::ball::{
-    apply_velocity_system, ball_warp_system, sweep_and_prune_collision_system,
-    update_sorted_balls_cache, Ball, Mass, Velocity,
-};
+use stuff::ball::{Ball, Mass, Velocity, WorldBounds};
 
 struct BallDefaults {
     starting_position: Vec3,
@@ -17,8 +13,8 @@ struct BallDefaults {
     color: bevy::color::Color,
 }
 
-const DEFAULT_WINDOW_WIDTH: f32 = 600.0;
-const DEFAULT_WINDOW_HEIGHT: f32 = 600.0;
+const DEFAULT_WORLD_WIDTH: f32 = 600.0;
+const DEFAULT_WORLD_HEIGHT: f32 = 600.0;
 
 const SPEED_SCALING: f32 = 1.0; //20.0;
 
@@ -89,22 +85,17 @@ pub struct Cli {
 
 fn main() {
     let cli = Cli::parse();
+    cli.common.reject_sweep::<Cli>();
 
     let mut app = stuff::setup::setup(&cli.common);
+    app.insert_resource(
+        cli.common
+            .world_bounds(WorldBounds::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT)),
+    );
+    stuff::setup::add_ball_physics_systems(&mut app, &cli.common);
     app.insert_resource(cli);
 
-    app.add_systems(Startup, setup).add_systems(
-        FixedUpdate,
-        (
-            apply_velocity_system,
-            //naive_ball_collision_system,
-            //sweep_and_prune_collision_system,
-            update_sorted_balls_cache,
-            sweep_and_prune_collision_system_with_cache,
-            ball_warp_system,
-        )
-            .chain(),
-    );
+    app.add_systems(Startup, setup);
 
     app.run();
 }
@@ -116,14 +107,9 @@ fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    mut window: Query<&mut Window>,
+    bounds: Res<WorldBounds>,
     _asset_server: Res<AssetServer>,
 ) {
-    window
-        .single_mut()
-        .resolution
-        .set(DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT);
-
     // Camera
     commands.spawn((
         Camera2dBundle {
@@ -137,8 +123,8 @@ fn setup(
         MyCamera,
     ));
 
-    let _half_width = window.single().width() / 2.0;
-    let _half_height = window.single().height() / 2.0;
+    let _half_width = bounds.half_size().x;
+    let _half_height = bounds.half_size().y;
 
     for ball in BALL_DEFAULTS {
         commands.spawn((
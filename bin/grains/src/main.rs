@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use bevy_prng::ChaCha8Rng;
+use bevy_rand::prelude::*;
+use clap::Parser;
+use stuff::ball::WorldBounds;
+use stuff::compound::CompoundBodyBuilder;
+use stuff::random::random_float;
+
+const DEFAULT_WORLD_WIDTH: f32 = 800.0;
+const DEFAULT_WORLD_HEIGHT: f32 = 600.0;
+
+const DEFAULT_NUM_GRAINS: usize = 300;
+
+const SPEED_SCALING: f32 = 1.0;
+
+#[derive(Parser, Resource)]
+pub struct Cli {
+    #[clap(flatten)]
+    pub common: stuff::cli::Cli,
+
+    #[clap(short, long, default_value_t = DEFAULT_NUM_GRAINS)]
+    num_grains: usize,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    cli.common.reject_sweep::<Cli>();
+
+    let mut app = stuff::setup::setup(&cli.common);
+    app.insert_resource(
+        cli.common
+            .world_bounds(WorldBounds::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT)),
+    );
+    stuff::setup::add_ball_physics_systems(&mut app, &cli.common);
+    app.insert_resource(cli);
+
+    app.add_systems(Startup, setup);
+
+    app.run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    bounds: Res<WorldBounds>,
+    cli: Res<Cli>,
+) {
+    // Camera
+    commands.spawn(Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(bevy::color::Color::srgb(0.0, 0.0, 0.0)),
+            ..default()
+        },
+        ..default()
+    });
+
+    let half_width = bounds.half_size().x;
+    let half_height = bounds.half_size().y;
+
+    const SPAWN_VELOCITY_MAX: f32 = 100.0 * SPEED_SCALING;
+
+    for i in 0..cli.num_grains {
+        let radius = 4.0 + 2.0 * random_float(&mut rng);
+
+        // Cycle through the grain shapes
+        let builder = match i % 3 {
+            0 => CompoundBodyBuilder::dumbbell(radius, 3.0 * radius, 1.0)
+                .color(Color::srgb(0.8, 0.7, 0.6)),
+            1 => CompoundBodyBuilder::peanut(radius, 1.0).color(Color::srgb(0.7, 0.6, 0.8)),
+            _ => {
+                // Irregular grain: a few circles scattered around a core
+                let mut builder = CompoundBodyBuilder::new()
+                    .circle(Vec2::ZERO, radius, 0.4)
+                    .color(Color::srgb(0.6, 0.8, 0.7));
+                for _ in 0..3 {
+                    let angle = random_float(&mut rng) * 2.0 * std::f32::consts::PI;
+                    let size = radius * (0.5 + 0.5 * random_float(&mut rng));
+                    builder = builder.circle(radius * Vec2::from_angle(angle), size, 0.2);
+                }
+                builder
+            }
+        };
+
+        let position = Vec2::new(
+            (2.0 * random_float(&mut rng) - 1.0) * half_width,
+            (2.0 * random_float(&mut rng) - 1.0) * half_height,
+        );
+        let speed = random_float(&mut rng) * SPAWN_VELOCITY_MAX;
+        let direction = random_float(&mut rng) * 2.0 * std::f32::consts::PI;
+        let spin = (2.0 * random_float(&mut rng) - 1.0) * 2.0;
+
+        builder.spawn(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            position,
+            speed * Vec2::from_angle(direction),
+            spin,
+        );
+    }
+}
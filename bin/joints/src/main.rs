@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+use clap::Parser;
+use stuff::ball::{Ball, Mass, Velocity, WorldBounds};
+use stuff::constraint::{DistanceJoint, Pin, Rope, Spring};
+
+const DEFAULT_WORLD_WIDTH: f32 = 800.0;
+const DEFAULT_WORLD_HEIGHT: f32 = 600.0;
+
+const RADIUS: f32 = 10.0;
+
+#[derive(Parser, Resource)]
+pub struct Cli {
+    #[clap(flatten)]
+    pub common: stuff::cli::Cli,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    cli.common.reject_sweep::<Cli>();
+
+    let mut app = stuff::setup::setup(&cli.common);
+    app.insert_resource(
+        cli.common
+            .world_bounds(WorldBounds::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT)),
+    );
+    stuff::setup::add_ball_physics_systems(&mut app, &cli.common);
+    app.insert_resource(cli);
+
+    app.add_systems(Startup, setup);
+
+    app.run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // Camera
+    commands.spawn(Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(bevy::color::Color::srgb(0.0, 0.0, 0.0)),
+            ..default()
+        },
+        ..default()
+    });
+
+    let mut spawn_ball = |position: Vec2, velocity: Vec2, color: Color| -> Entity {
+        commands
+            .spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(Circle::default()).into(),
+                    material: materials.add(color),
+                    transform: Transform::from_translation(position.extend(0.0))
+                        .with_scale(Vec2::splat(2.0 * RADIUS).extend(1.0)),
+                    ..default()
+                },
+                Ball,
+                Velocity(velocity),
+                Mass(1.0),
+            ))
+            .id()
+    };
+
+    let mut joints = Vec::new();
+    let mut springs = Vec::new();
+    let mut ropes = Vec::new();
+    let mut pins = Vec::new();
+
+    // Pendulum: a ball on a pin, set spinning around its anchor
+    let anchor = Vec2::new(-250.0, 150.0);
+    let bob = spawn_ball(
+        anchor + Vec2::new(0.0, -80.0),
+        Vec2::new(150.0, 0.0),
+        Color::srgb(0.8, 0.7, 0.6),
+    );
+    pins.push(Pin {
+        ball: bob,
+        anchor,
+        length: 80.0,
+    });
+
+    // Chain: rigid links hanging from a pin, with the free end flicked sideways
+    let anchor = Vec2::new(0.0, 250.0);
+    let mut previous = None;
+    for i in 0..8 {
+        let position = anchor - Vec2::new(0.0, 2.0 * RADIUS * (i + 1) as f32);
+        let velocity = if i == 7 {
+            Vec2::new(200.0, 0.0)
+        } else {
+            Vec2::ZERO
+        };
+        let link = spawn_ball(position, velocity, Color::srgb(0.7, 0.6, 0.8));
+        match previous {
+            None => pins.push(Pin {
+                ball: link,
+                anchor,
+                length: 2.0 * RADIUS,
+            }),
+            Some(previous) => joints.push(DistanceJoint {
+                a: previous,
+                b: link,
+                length: 2.0 * RADIUS,
+            }),
+        }
+        previous = Some(link);
+    }
+
+    // Newton's cradle: touching balls on equal pins, with the first one swung in
+    for i in 0..5 {
+        let anchor = Vec2::new(200.0 + 2.0 * RADIUS * i as f32, 150.0);
+        let velocity = if i == 0 {
+            Vec2::new(100.0, 0.0)
+        } else {
+            Vec2::ZERO
+        };
+        let ball = spawn_ball(
+            anchor - Vec2::new(0.0, 100.0),
+            velocity,
+            Color::srgb(0.8, 0.8, 0.8),
+        );
+        pins.push(Pin {
+            ball,
+            anchor,
+            length: 100.0,
+        });
+    }
+
+    // Soft lattice: a grid of balls held together by damped springs
+    let origin = Vec2::new(-250.0, -200.0);
+    let spacing = 3.0 * RADIUS;
+    let size = 4;
+    let mut grid = Vec::new();
+    for row in 0..size {
+        for column in 0..size {
+            let position = origin + spacing * Vec2::new(column as f32, row as f32);
+            grid.push(spawn_ball(
+                position,
+                Vec2::new(60.0, 20.0),
+                Color::srgb(0.6, 0.8, 0.7),
+            ));
+        }
+    }
+    for row in 0..size {
+        for column in 0..size {
+            let here = grid[row * size + column];
+            let mut link = |other: Entity, rest_length: f32| {
+                springs.push(Spring {
+                    a: here,
+                    b: other,
+                    rest_length,
+                    stiffness: 200.0,
+                    damping: 2.0,
+                })
+            };
+            if column + 1 < size {
+                link(grid[row * size + column + 1], spacing);
+            }
+            if row + 1 < size {
+                link(grid[(row + 1) * size + column], spacing);
+            }
+            if row + 1 < size && column + 1 < size {
+                link(
+                    grid[(row + 1) * size + column + 1],
+                    spacing * std::f32::consts::SQRT_2,
+                );
+            }
+        }
+    }
+
+    // Rope: two balls flying apart until the rope goes taut
+    let a = spawn_ball(
+        Vec2::new(150.0, -150.0),
+        Vec2::new(-80.0, 30.0),
+        Color::srgb(0.9, 0.5, 0.5),
+    );
+    let b = spawn_ball(
+        Vec2::new(200.0, -150.0),
+        Vec2::new(80.0, -30.0),
+        Color::srgb(0.9, 0.5, 0.5),
+    );
+    ropes.push(Rope {
+        a,
+        b,
+        max_length: 150.0,
+    });
+
+    commands.spawn_batch(joints);
+    commands.spawn_batch(springs);
+    commands.spawn_batch(ropes);
+    commands.spawn_batch(pins);
+}
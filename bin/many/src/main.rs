@@ -5,12 +5,7 @@ use bevy_rand::prelude::*;
 use clap::Parser;
 use scarlet::colormap::{ColorMap, GradientColorMap};
 use scarlet::prelude::*;
-#[allow(unused_imports)]
-use stuff::ball::{
-    apply_velocity_system, ball_warp_system, naive_ball_collision_system,
-    sweep_and_prune_collision_system, sweep_and_prune_collision_system_with_cache,
-    update_sorted_balls_cache, Ball, Mass, Velocity,
-};
+use stuff::ball::{Ball, Mass, Velocity, WorldBounds};
 use stuff::my_color::MyColor;
 use stuff::random::random_float;
 
@@ -23,8 +18,8 @@ struct BallDefaults {
     color: bevy::color::Color,
 }
 
-const DEFAULT_WINDOW_WIDTH: f32 = 1920.0;
-const DEFAULT_WINDOW_HEIGHT: f32 = 1080.0;
+const DEFAULT_WORLD_WIDTH: f32 = 1920.0;
+const DEFAULT_WORLD_HEIGHT: f32 = 1080.0;
 
 // 1,000 balls, seed = 0, 500 fixed frames @ 1920x1080 = 2,003 collisions
 //
@@ -49,6 +44,12 @@ const DEFAULT_WINDOW_HEIGHT: f32 = 1080.0;
 //  10000: 38 fps   @ 1920x1080
 //
 // Does not seem to be GPU limited, as triangles render in the same time as circles
+//
+// To measure this for yourself (choose the broadphase for a normal run with --broadphase):
+//   cargo run --release --bin many -- sweep --balls 2500,4000,10000 --broadphases naive,sap-cached
+//
+// Or with no window or renderer at all, e.g. on a machine without a display:
+//   cargo run --release --bin many -- --headless sweep --balls 2500,4000,10000
 const DEFAULT_NUM_BALLS: usize = 10000;
 
 const SPEED_SCALING: f32 = 1.0; //20.0;
@@ -66,20 +67,14 @@ fn main() {
     let cli = Cli::parse();
 
     let mut app = stuff::setup::setup(&cli.common);
+    app.insert_resource(
+        cli.common
+            .world_bounds(WorldBounds::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT)),
+    );
+    stuff::setup::add_ball_physics_systems(&mut app, &cli.common);
     app.insert_resource(cli);
 
-    app.add_systems(Startup, setup).add_systems(
-        FixedUpdate,
-        (
-            apply_velocity_system,
-            //naive_ball_collision_system,
-            //sweep_and_prune_collision_system,
-            update_sorted_balls_cache,
-            sweep_and_prune_collision_system_with_cache,
-            ball_warp_system,
-        )
-            .chain(),
-    );
+    app.add_systems(Startup, setup);
 
     app.run();
 }
@@ -96,15 +91,10 @@ fn setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
-    mut window: Query<&mut Window>,
+    bounds: Res<WorldBounds>,
     _asset_server: Res<AssetServer>,
     cli: Res<Cli>,
 ) {
-    window
-        .single_mut()
-        .resolution
-        .set(DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT);
-
     // Camera
     commands.spawn((
         Camera2dBundle {
@@ -118,8 +108,8 @@ fn setup(
         MyCamera,
     ));
 
-    let half_width = window.single().width() / 2.0;
-    let half_height = window.single().height() / 2.0;
+    let half_width = bounds.half_size().x;
+    let half_height = bounds.half_size().y;
 
     let color_map = GradientColorMap::new_linear(
         RGBColor {
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+use bevy_prng::ChaCha8Rng;
+use bevy_rand::prelude::*;
+use clap::Parser;
+use stuff::ball::{Ball, Mass, Velocity, WorldBounds};
+use stuff::brownian::Brownian;
+use stuff::random::random_float;
+use stuff::thermostat::maxwell_boltzmann_velocity;
+
+// Brownian motion of a few large "pollen" balls, either kicked about by a gas of
+// small molecules, or (with --implicit) by an implicit solvent.
+//
+// The explicit gas cools down unless collisions are elastic or a thermostat is on:
+//   cargo run --bin pollen -- --contact-model elastic
+//   cargo run --bin pollen -- --implicit
+
+const DEFAULT_WORLD_WIDTH: f32 = 800.0;
+const DEFAULT_WORLD_HEIGHT: f32 = 600.0;
+
+const DEFAULT_NUM_POLLEN: usize = 5;
+const DEFAULT_NUM_MOLECULES: usize = 3000;
+
+const POLLEN_RADIUS: f32 = 20.0;
+const POLLEN_MASS: f32 = 100.0;
+const MOLECULE_RADIUS: f32 = 2.0;
+const MOLECULE_MASS: f32 = 1.0;
+
+#[derive(Parser, Resource)]
+pub struct Cli {
+    #[clap(flatten)]
+    pub common: stuff::cli::Cli,
+
+    #[clap(long, default_value_t = DEFAULT_NUM_POLLEN)]
+    num_pollen: usize,
+
+    #[clap(short, long, default_value_t = DEFAULT_NUM_MOLECULES)]
+    num_molecules: usize,
+
+    /// Replace the molecules with an implicit solvent
+    #[clap(long)]
+    implicit: bool,
+
+    /// Temperature of the molecules or implicit solvent
+    #[clap(long, default_value_t = 5000.0)]
+    bath_temperature: f32,
+
+    /// Friction coefficient of the implicit solvent
+    #[clap(long, default_value_t = 50.0)]
+    friction: f32,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    cli.common.reject_sweep::<Cli>();
+
+    let mut app = stuff::setup::setup(&cli.common);
+    app.insert_resource(
+        cli.common
+            .world_bounds(WorldBounds::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT)),
+    );
+    stuff::setup::add_ball_physics_systems(&mut app, &cli.common);
+    app.insert_resource(cli);
+
+    app.add_systems(Startup, setup);
+
+    app.run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    bounds: Res<WorldBounds>,
+    cli: Res<Cli>,
+) {
+    // Camera
+    commands.spawn(Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(bevy::color::Color::srgb(0.0, 0.0, 0.0)),
+            ..default()
+        },
+        ..default()
+    });
+
+    let half_width = bounds.half_size().x;
+    let half_height = bounds.half_size().y;
+
+    let random_position = |rng: &mut ResMut<GlobalEntropy<ChaCha8Rng>>| {
+        Vec2::new(
+            (2.0 * random_float(rng) - 1.0) * half_width,
+            (2.0 * random_float(rng) - 1.0) * half_height,
+        )
+    };
+
+    let pollen_material = materials.add(Color::srgb(1.0, 0.8, 0.2));
+    for _ in 0..cli.num_pollen {
+        let mut pollen = commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(Circle::default()).into(),
+                material: pollen_material.clone(),
+                transform: Transform::from_translation(random_position(&mut rng).extend(1.0))
+                    .with_scale(Vec2::splat(2.0 * POLLEN_RADIUS).extend(1.0)),
+                ..default()
+            },
+            Ball,
+            Velocity(Vec2::ZERO),
+            Mass(POLLEN_MASS),
+        ));
+        if cli.implicit {
+            pollen.insert(Brownian::new(cli.friction, cli.bath_temperature));
+        }
+    }
+
+    if cli.implicit {
+        return;
+    }
+
+    let molecule_material = materials.add(Color::srgb(0.3, 0.3, 0.6));
+    for _ in 0..cli.num_molecules {
+        let velocity = maxwell_boltzmann_velocity(&mut rng, cli.bath_temperature, MOLECULE_MASS);
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(Circle::default()).into(),
+                material: molecule_material.clone(),
+                transform: Transform::from_translation(random_position(&mut rng).extend(0.0))
+                    .with_scale(Vec2::splat(2.0 * MOLECULE_RADIUS).extend(1.0)),
+                ..default()
+            },
+            Ball,
+            Velocity(velocity),
+            Mass(MOLECULE_MASS),
+        ));
+    }
+}
@@ -5,15 +5,17 @@ use bevy_rand::prelude::*;
 use clap::Parser;
 use scarlet::colormap::{ColorMap, GradientColorMap};
 use scarlet::prelude::*;
-use stuff::ball::SortedBallsCache;
 #[allow(unused_imports)]
-use stuff::ball::{
-    apply_velocity_system, ball_warp_system, naive_ball_collision_system,
-    sweep_and_prune_collision_system, sweep_and_prune_collision_system_with_cache,
-    update_sorted_balls_cache, Ball, Mass, Velocity,
-};
+use stuff::ball::{Ball, Mass, Velocity, WorldBounds};
 use stuff::my_color::MyColor;
 use stuff::random::random_float;
+use stuff::soft_body::SoftBodyBuilder;
+
+// To see which fragments of the disk hold together after an impact:
+//   cargo run --bin smash -- --clusters 10 --color-clusters
+//
+// And to characterise the packing the disk settles into before you fire at it:
+//   cargo run --bin smash -- --packing 60 --packing-output packing.csv
 
 struct BallDefaults {
     starting_position: Vec3,
@@ -24,8 +26,8 @@ struct BallDefaults {
     color: bevy::color::Color,
 }
 
-const DEFAULT_WINDOW_WIDTH: f32 = 800.0;
-const DEFAULT_WINDOW_HEIGHT: f32 = 600.0;
+const DEFAULT_WORLD_WIDTH: f32 = 800.0;
+const DEFAULT_WORLD_HEIGHT: f32 = 600.0;
 
 //const NUM_BALLS: usize = 2000;
 const DEFAULT_NUM_BALLS: usize = 2000;
@@ -44,22 +46,15 @@ pub struct Cli {
 fn main() {
     let cli = Cli::parse();
     let mut app = stuff::setup::setup(&cli.common);
+    app.insert_resource(
+        cli.common
+            .world_bounds(WorldBounds::new(DEFAULT_WORLD_WIDTH, DEFAULT_WORLD_HEIGHT)),
+    );
+    stuff::setup::add_ball_physics_systems(&mut app, &cli.common);
     app.insert_resource(cli);
 
     app.add_systems(Startup, setup)
-        .add_systems(Update, (handle_input,))
-        .add_systems(
-            FixedUpdate,
-            (
-                apply_velocity_system,
-                //naive_ball_collision_system,
-                //sweep_and_prune_collision_system,
-                update_sorted_balls_cache,
-                sweep_and_prune_collision_system_with_cache,
-                ball_warp_system,
-            )
-                .chain(),
-        );
+        .add_systems(Update, (handle_input,));
 
     app.run();
 }
@@ -76,15 +71,10 @@ fn setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
-    mut window: Query<&mut Window>,
+    bounds: Res<WorldBounds>,
     _asset_server: Res<AssetServer>,
     cli: Res<Cli>,
 ) {
-    window
-        .single_mut()
-        .resolution
-        .set(DEFAULT_WINDOW_WIDTH, DEFAULT_WINDOW_HEIGHT);
-
     // Camera
     commands.spawn((
         Camera2dBundle {
@@ -98,8 +88,8 @@ fn setup(
         MyCamera,
     ));
 
-    let _half_width = window.single().width() / 2.0;
-    let _half_height = window.single().height() / 2.0;
+    let _half_width = bounds.half_size().x;
+    let _half_height = bounds.half_size().y;
 
     let spawn_radius_max = 2.0 * _half_width / 3.0;
     let spawn_velocity_max = 100.0 * SPEED_SCALING;
@@ -174,12 +164,11 @@ fn handle_input(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
-    window: Query<&Window>,
-    mut ball_cache: ResMut<SortedBallsCache>,
+    bounds: Res<WorldBounds>,
 ) {
     if keyboard_input.just_pressed(KeyCode::Enter) {
-        let width = window.single().width();
-        let height = window.single().height();
+        let width = bounds.width();
+        let height = bounds.height();
 
         let radius = 1.2 * f32::min(width, height) / 2.0;
         let angle = random_float(&mut rng) * 2.0 * std::f32::consts::PI;
@@ -201,7 +190,7 @@ fn handle_input(
         let transform = Transform::from_translation(ball.starting_position)
             .with_scale(Vec2::splat(ball.diameter).extend(1.0));
 
-        let entity_commands = commands.spawn((
+        commands.spawn((
             MaterialMesh2dBundle {
                 mesh: meshes.add(Circle::default()).into(),
                 material: materials.add(ball.color),
@@ -212,7 +201,30 @@ fn handle_input(
             Velocity(ball.initial_direction.normalize() * ball.speed),
             Mass(ball.mass),
         ));
+    }
 
-        ball_cache.add(entity_commands.id(), transform);
+    // Fire a soft blob instead of a solid ball
+    if keyboard_input.just_pressed(KeyCode::KeyB) {
+        let width = bounds.width();
+        let height = bounds.height();
+
+        let blob = SoftBodyBuilder::ring(24)
+            .ball(4.0, 0.5)
+            .springs(400.0, 4.0)
+            .pressure(2000.0)
+            .color(bevy::prelude::Color::srgb(1.0, 0.5, 0.0));
+
+        // Start just inside the edge, so the blob doesn't begin life wrapped around it
+        let radius = f32::min(width, height) / 2.0 - blob.bounding_radius();
+        let angle = random_float(&mut rng) * 2.0 * std::f32::consts::PI;
+        let spawn = radius * Vec2::from_angle(angle);
+
+        blob.spawn(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            spawn,
+            -spawn.normalize() * 250.0 * SPEED_SCALING,
+        );
     }
 }
@@ -0,0 +1,87 @@
+use crate::ball::{Ball, Mass, PhysicsSet, Velocity};
+use crate::setup::Headless;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::color::Mix;
+use bevy::prelude::{
+    Assets, ColorMaterial, Commands, Entity, Event, EventReader, Handle, IntoSystemConfigs, Query,
+    Res, ResMut, Transform, With,
+};
+use bevy::utils::HashSet;
+
+/// Two balls that should become one, sent by the collision systems when
+/// `CollisionResponse::Accrete` is in use.
+#[derive(Event)]
+pub struct MergeEvent {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+/// Carries out merges requested by the collision systems.
+#[derive(Default)]
+pub struct AccretionPlugin;
+
+impl Plugin for AccretionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MergeEvent>().add_systems(
+            FixedUpdate,
+            accretion_system.in_set(PhysicsSet::PostCollide),
+        );
+    }
+}
+
+/// Replace each merging pair with a single ball at their centre of mass, conserving
+/// mass, momentum and area. The first ball of the pair survives and the second is
+/// despawned.
+pub fn accretion_system(
+    mut commands: Commands,
+    mut events: EventReader<MergeEvent>,
+    mut balls: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut Mass,
+            &mut Handle<ColorMaterial>,
+        ),
+        With<Ball>,
+    >,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    headless: Option<Res<Headless>>,
+) {
+    // A ball can touch several others in one step; once absorbed it is gone
+    let mut absorbed = HashSet::new();
+
+    for event in events.read() {
+        if absorbed.contains(&event.a) || absorbed.contains(&event.b) {
+            continue;
+        }
+
+        let Ok([(mut t1, mut v1, mut m1, mut h1), (t2, v2, m2, h2)]) =
+            balls.get_many_mut([event.a, event.b])
+        else {
+            continue;
+        };
+
+        let mass = m1.0 + m2.0;
+        let position = (m1.0 * t1.translation + m2.0 * t2.translation) / mass;
+        let velocity = (m1.0 * v1.0 + m2.0 * v2.0) / mass;
+
+        // Area is conserved, so the diameters add in quadrature
+        let diameter = (t1.scale.x * t1.scale.x + t2.scale.x * t2.scale.x).sqrt();
+
+        // Blend the colours by mass, unless there's nothing to see them with
+        if headless.is_none() {
+            if let (Some(c1), Some(c2)) = (materials.get(&*h1), materials.get(&*h2)) {
+                let color = c1.color.mix(&c2.color, m2.0 / mass);
+                *h1 = materials.add(color);
+            }
+        }
+
+        t1.translation = position;
+        t1.scale = t1.scale.with_x(diameter).with_y(diameter);
+        v1.0 = velocity;
+        m1.0 = mass;
+
+        commands.entity(event.b).despawn();
+        absorbed.insert(event.b);
+    }
+}
@@ -1,7 +1,20 @@
-use bevy::math::Vec2;
+use crate::accretion::MergeEvent;
+use crate::benchmark::{CollisionStopwatch, PhaseTimings};
+use crate::brownian::Brownian;
+use crate::compound::{welded, CompoundBodies, CompoundBody, CompoundPart};
+use crate::conserved_quantities_diagnostics_plugin::PotentialEnergy;
+use crate::contact::{ActiveContactModel, Contact};
+use crate::fragmentation::{FragmentationConfig, ImpactEvent};
+use crate::pressure::Virial;
+use crate::sleep::Sleeping;
+use bevy::ecs::query::Has;
+use bevy::ecs::system::SystemParam;
+use bevy::math::{IVec2, Vec2};
 use bevy::prelude::{
-    Component, Deref, DerefMut, Entity, Query, Res, ResMut, Resource, Time, Transform, Window, With,
+    Added, Component, Deref, DerefMut, Entity, EventWriter, Or, Query, Res, ResMut, Resource,
+    SystemSet, Time, Transform, With, Without,
 };
+use bevy::utils::HashMap;
 
 #[derive(Component, Deref, DerefMut)]
 pub struct Velocity(pub Vec2);
@@ -15,10 +28,201 @@ pub struct Ball;
 #[derive(Resource, Default)]
 pub struct Stats {
     pub num_collisions: usize,
+    /// Per-ball counters, kept by `collision_stats::collision_stats_system` when enabled
+    pub per_ball: HashMap<Entity, BallStats>,
+    /// Time covered by `per_ball`
+    pub elapsed: f32,
 }
 
+#[derive(Default, Clone)]
+pub struct BallStats {
+    pub collisions: usize,
+    /// Total distance travelled
+    pub distance: f32,
+    /// Distance travelled since the last collision
+    pub free_path: f32,
+    /// Sum and number of the completed paths between successive collisions
+    pub total_free_path: f32,
+    pub num_free_paths: usize,
+}
+
+impl BallStats {
+    pub fn mean_free_path(&self) -> Option<f32> {
+        (self.num_free_paths > 0).then(|| self.total_free_path / self.num_free_paths as f32)
+    }
+}
+
+/// Pairs of balls found to be touching during the most recent collision step.
+#[derive(Resource, Default)]
+pub struct Contacts {
+    pub pairs: Vec<(Entity, Entity)>,
+}
+
+/// How a pair of touching balls responds to the collision.
+#[derive(Resource, Default)]
+pub enum CollisionResponse {
+    /// Bounce apart according to the `ActiveContactModel`
+    #[default]
+    Bounce,
+    /// Merge into one ball when approaching slower than `stickiness`, otherwise bounce.
+    /// Merges are carried out by `accretion::accretion_system`.
+    Accrete { stickiness: f32 },
+    /// Pass through each other. For soft potentials such as Lennard-Jones, which
+    /// provide their own repulsion.
+    None,
+}
+
+/// One side of a collision, as handed to `CollisionHooks::respond`.
+pub type CollisionBody<'a> = (
+    Entity,
+    &'a mut Transform,
+    &'a mut Velocity,
+    &'a Mass,
+    Option<&'a CompoundPart>,
+);
+
+/// Everything the collision systems need to respond to a pair of touching balls.
+#[derive(SystemParam)]
+pub struct CollisionHooks<'w, 's> {
+    model: Res<'w, ActiveContactModel>,
+    time: Res<'w, Time>,
+    response: Res<'w, CollisionResponse>,
+    fragmentation: Option<Res<'w, FragmentationConfig>>,
+    merges: EventWriter<'w, MergeEvent>,
+    impacts: EventWriter<'w, ImpactEvent>,
+    virial: Option<ResMut<'w, Virial>>,
+    potential: Option<ResMut<'w, PotentialEnergy>>,
+    sleeping: Query<'w, 's, (), With<Sleeping>>,
+    bodies: CompoundBodies<'w, 's>,
+}
+
+impl CollisionHooks<'_, '_> {
+    /// Response hook, called by every broadphase for each pair of touching balls.
+    pub fn respond(&mut self, a: CollisionBody, b: CollisionBody) {
+        let (e1, t1, v1, m1, c1) = a;
+        let (e2, t2, v2, m2, c2) = b;
+
+        let x1 = t1.translation.truncate();
+        let x2 = t2.translation.truncate();
+
+        // Use the x scaling as the diameter
+        let r1 = t1.scale.x / 2.0;
+        let r2 = t2.scale.x / 2.0;
+
+        let distance = x1.distance(x2);
+        let normal = if distance > 0.0 {
+            (x2 - x1) / distance
+        } else {
+            Vec2::X
+        };
+        let approach_speed = (v1.0 - v2.0).dot(normal);
+
+        match *self.response {
+            CollisionResponse::Bounce => (),
+            CollisionResponse::Accrete { stickiness } => {
+                // Parts of rigid bodies keep their shape
+                if c1.is_none() && c2.is_none() && approach_speed < stickiness {
+                    self.merges.send(MergeEvent { a: e1, b: e2 });
+                    return;
+                }
+            }
+            CollisionResponse::None => return,
+        }
+
+        // A part of a compound body is only as hard to push as its body is at the
+        // contact, which is less than the whole mass it carries once the body can turn
+        let mass1 = c1
+            .and_then(|part| self.bodies.effective_mass(part, x1, normal))
+            .unwrap_or(m1.0);
+        let mass2 = c2
+            .and_then(|part| self.bodies.effective_mass(part, x2, normal))
+            .unwrap_or(m2.0);
+
+        let contact = Contact {
+            normal,
+            depth: (r1 + r2) - distance,
+            relative_velocity: v2.0 - v1.0,
+            m1: mass1,
+            m2: mass2,
+            r1,
+            r2,
+        };
+        let response = self.model.0.respond(&contact, self.time.delta_seconds());
+        if let Some(potential) = &mut self.potential {
+            potential.add("contacts", self.model.0.potential_energy(&contact));
+        }
+
+        let energy_before = 0.5 * m1.0 * v1.length_squared() + 0.5 * m2.0 * v2.length_squared();
+
+        // A sleeping ball stays where it is, and the one that ran into it takes the whole
+        // correction. It only wakes if the impulse is enough to get it moving.
+        let (correction1, correction2) =
+            match (self.sleeping.contains(e1), self.sleeping.contains(e2)) {
+                (true, false) => (Vec2::ZERO, response.correction2 - response.correction1),
+                (false, true) => (response.correction1 - response.correction2, Vec2::ZERO),
+                _ => (response.correction1, response.correction2),
+            };
+        // Parts take the impulse, and the positional impulse behind their correction, at
+        // the mass they carry, and compound_response_system hands both on to the body.
+        // An ordinary ball just takes the correction.
+        t1.translation += (correction1 * mass1 / m1.0).extend(0.0);
+        t2.translation += (correction2 * mass2 / m2.0).extend(0.0);
+        v1.0 -= response.impulse / m1.0;
+        v2.0 += response.impulse / m2.0;
+
+        if let Some(virial) = &mut self.virial {
+            virial.0 += (x2 - x1).dot(response.impulse);
+        }
+
+        if let Some(config) = &self.fragmentation {
+            // Energy of the head-on part of the collision, in the centre of mass frame
+            let reduced_mass = m1.0 * m2.0 / (m1.0 + m2.0);
+            let energy = 0.5 * reduced_mass * approach_speed.max(0.0).powi(2);
+            if energy > config.energy_threshold {
+                let energy_after =
+                    0.5 * m1.0 * v1.length_squared() + 0.5 * m2.0 * v2.length_squared();
+                self.impacts.send(ImpactEvent {
+                    a: e1,
+                    b: e2,
+                    energy,
+                    dissipated: (energy_before - energy_after).max(0.0),
+                });
+            }
+        }
+    }
+}
+
+/// Query data used by the collision systems.
+type CollisionQueryData = (
+    Entity,
+    &'static mut Transform,
+    &'static mut Velocity,
+    &'static Mass,
+    Has<Sleeping>,
+    Option<&'static CompoundPart>,
+);
+
+/// Ordering of the physics step within FixedUpdate.
+///
+/// The sets are chained by `setup::setup`, so plugins can slot their systems in
+/// relative to the collision systems without knowing which broadphase is in use.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PhysicsSet {
+    Integrate,
+    Collide,
+    Constrain,
+    PostCollide,
+    Boundary,
+}
+
+/// Sleeping balls stay put, and Brownian balls have their own integrator.
+type IntegrateFilter = (Without<Sleeping>, Without<Brownian>);
+
 // Systems
-pub fn apply_velocity_system(mut query: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
+pub fn apply_velocity_system(
+    mut query: Query<(&mut Transform, &Velocity), IntegrateFilter>,
+    time: Res<Time>,
+) {
     // In FixedUpdate context, time.delta_seconds() is the fixed time step.
     // https://bevy-cheatbook.github.io/fundamentals/fixed-timestep.html
 
@@ -30,36 +234,198 @@ pub fn apply_velocity_system(mut query: Query<(&mut Transform, &Velocity)>, time
     }
 }
 
-pub fn ball_warp_system(mut query: Query<&mut Transform, With<Ball>>, window: Query<&Window>) {
-    let window = window.single();
-    let half_width = window.width() / 2.0;
-    let half_height = window.height() / 2.0;
+/// The box the balls live in, centred on the origin. It comes from configuration,
+/// not from the window, so that the physics doesn't need one.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct WorldBounds {
+    pub size: Vec2,
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        WorldBounds::new(800.0, 600.0)
+    }
+}
+
+impl WorldBounds {
+    pub fn new(width: f32, height: f32) -> WorldBounds {
+        WorldBounds {
+            size: Vec2::new(width, height),
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.size.x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.size.y
+    }
+
+    pub fn half_size(&self) -> Vec2 {
+        self.size / 2.0
+    }
+
+    pub fn area(&self) -> f32 {
+        self.size.x * self.size.y
+    }
+}
+
+/// What happens to balls that reach the edge of the world.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// Leave one side and come back in the other (`ball_warp_system`)
+    #[default]
+    Wrap,
+    /// Bounce elastically off the edges (`ball_wall_system`)
+    Reflect,
+}
+
+impl Boundary {
+    /// The box that separations wrap around, if balls wrap.
+    pub fn periodic_box(&self, bounds: &WorldBounds) -> Option<Vec2> {
+        (*self == Boundary::Wrap).then_some(bounds.size)
+    }
+}
+
+/// Separation from `b` to `a`, to the nearest periodic image of `b` if `box_size` is
+/// given. Balls that are linked to each other may be on opposite sides of the box
+/// just after one of them has wrapped.
+pub fn separation(a: Vec2, b: Vec2, box_size: Option<Vec2>) -> Vec2 {
+    let r = a - b;
+    match box_size {
+        Some(size) => r - size * (r / size).round(),
+        None => r,
+    }
+}
+
+/// Momentum given to each wall by `ball_wall_system` since it was last reset.
+#[derive(Resource, Default)]
+pub struct WallImpulses {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+/// Counts how many times `ball_warp_system` has sent a ball around the box, so that
+/// its trajectory can be followed without the jumps.
+#[derive(Component, Default)]
+pub struct PeriodicImage {
+    /// Net number of crossings in each direction
+    pub images: IVec2,
+    /// Total distance the ball has been moved by warping
+    pub offset: Vec2,
+}
+
+impl PeriodicImage {
+    /// Where the ball would be if it had never been warped.
+    pub fn unwrapped(&self, transform: &Transform) -> Vec2 {
+        transform.translation.truncate() - self.offset
+    }
+}
+
+/// Compound bodies are warped as a whole, and their parts follow.
+type WarpFilter = (Or<(With<Ball>, With<CompoundBody>)>, Without<CompoundPart>);
+
+pub fn ball_warp_system(
+    mut query: Query<(&mut Transform, Option<&mut PeriodicImage>), WarpFilter>,
+    bounds: Res<WorldBounds>,
+    boundary: Res<Boundary>,
+) {
+    if *boundary != Boundary::Wrap {
+        return;
+    }
+
+    let half_width = bounds.width() / 2.0;
+    let half_height = bounds.height() / 2.0;
 
-    for mut transform in &mut query {
+    for (mut transform, image) in &mut query {
         let radius = transform.scale.x / 2.0;
+        let before = transform.translation.truncate();
+        let mut crossing = IVec2::ZERO;
 
         if transform.translation.x > half_width + radius {
             transform.translation.x = -half_width - radius;
+            crossing.x = 1;
         } else if transform.translation.x < -half_width - radius {
             transform.translation.x = half_width + radius;
+            crossing.x = -1;
         }
 
         if transform.translation.y > half_height + radius {
             transform.translation.y = -half_height - radius;
+            crossing.y = 1;
         } else if transform.translation.y < -half_height - radius {
             transform.translation.y = half_height + radius;
+            crossing.y = -1;
+        }
+
+        if let Some(mut image) = image {
+            if crossing != IVec2::ZERO {
+                image.images += crossing;
+                image.offset += transform.translation.truncate() - before;
+            }
+        }
+    }
+}
+
+pub fn ball_wall_system(
+    mut query: Query<(&mut Transform, &mut Velocity, &Mass), WarpFilter>,
+    bounds: Res<WorldBounds>,
+    mut walls: ResMut<WallImpulses>,
+) {
+    let half_width = bounds.width() / 2.0;
+    let half_height = bounds.height() / 2.0;
+
+    for (mut transform, mut velocity, mass) in &mut query {
+        let radius = transform.scale.x / 2.0;
+
+        // Put the ball back inside and reverse its velocity; the wall takes twice the
+        // normal momentum
+        if transform.translation.x > half_width - radius && velocity.x > 0.0 {
+            transform.translation.x = half_width - radius;
+            walls.right += 2.0 * mass.0 * velocity.x;
+            velocity.x = -velocity.x;
+        } else if transform.translation.x < -half_width + radius && velocity.x < 0.0 {
+            transform.translation.x = -half_width + radius;
+            walls.left -= 2.0 * mass.0 * velocity.x;
+            velocity.x = -velocity.x;
+        }
+
+        if transform.translation.y > half_height - radius && velocity.y > 0.0 {
+            transform.translation.y = half_height - radius;
+            walls.top += 2.0 * mass.0 * velocity.y;
+            velocity.y = -velocity.y;
+        } else if transform.translation.y < -half_height + radius && velocity.y < 0.0 {
+            transform.translation.y = -half_height + radius;
+            walls.bottom -= 2.0 * mass.0 * velocity.y;
+            velocity.y = -velocity.y;
         }
     }
 }
 
 pub fn naive_ball_collision_system(
-    mut query: Query<(&mut Transform, &mut Velocity, &Mass), With<Ball>>,
+    mut query: Query<CollisionQueryData, With<Ball>>,
     mut stats: ResMut<Stats>,
+    mut contacts: ResMut<Contacts>,
+    mut hooks: CollisionHooks,
+    timings: Option<ResMut<PhaseTimings>>,
 ) {
-    // Naive O(n^2) collision detection, comparing every particle with every other particle
+    contacts.pairs.clear();
+
+    // Naive O(n^2) collision detection, comparing every particle with every other particle.
+    let mut stopwatch = CollisionStopwatch::start(&timings);
     let mut combinations = query.iter_combinations_mut();
 
-    while let Some([(mut t1, mut v1, m1), (mut t2, mut v2, m2)]) = combinations.fetch_next() {
+    while let Some([(e1, mut t1, mut v1, m1, s1, c1), (e2, mut t2, mut v2, m2, s2, c2)]) =
+        combinations.fetch_next()
+    {
+        if (s1 && s2) || welded(c1, c2) {
+            // Both asleep, nothing can change; or both parts of the same rigid body
+            continue;
+        }
+
         let x1 = t1.translation.truncate();
         let x2 = t2.translation.truncate();
 
@@ -72,27 +438,42 @@ pub fn naive_ball_collision_system(
         let distance = x1.distance(x2);
         if distance < r1 + r2 {
             // Collision detected
-            perform_collision(&mut t1, &mut v1, m1, &mut t2, &mut v2, m2);
+            stopwatch.respond(|| {
+                hooks.respond(
+                    (e1, &mut t1, &mut v1, m1, c1),
+                    (e2, &mut t2, &mut v2, m2, c2),
+                )
+            });
             stats.num_collisions += 1;
+            contacts.pairs.push((e1, e2));
         }
     }
+
+    stopwatch.stop(timings);
 }
 
 pub fn sweep_and_prune_collision_system(
-    mut query: Query<(&mut Transform, &mut Velocity, &Mass), With<Ball>>,
+    mut query: Query<CollisionQueryData, With<Ball>>,
     mut stats: ResMut<Stats>,
+    mut contacts: ResMut<Contacts>,
+    mut hooks: CollisionHooks,
+    timings: Option<ResMut<PhaseTimings>>,
 ) {
+    contacts.pairs.clear();
+
     // Sweep and prune collision detection
     // https://leanrada.com/notes/sweep-and-prune/
 
+    let mut stopwatch = CollisionStopwatch::start(&timings);
+
     // Sort particles by left x bound
     // TODO exploit temporal coherence by persisting sorted order
     let mut particles = query.iter_mut().collect::<Vec<_>>();
 
     // O(n log n)
     particles.sort_by(|a, b| {
-        let ax = a.0.translation.x - a.0.scale.x / 2.0; // radius is scale / 2.0
-        let bx = b.0.translation.x - b.0.scale.x / 2.0;
+        let ax = a.1.translation.x - a.1.scale.x / 2.0; // radius is scale / 2.0
+        let bx = b.1.translation.x - b.1.scale.x / 2.0;
         ax.partial_cmp(&bx).unwrap()
     });
 
@@ -100,17 +481,21 @@ pub fn sweep_and_prune_collision_system(
     for i in 0..particles.len() {
         let (left, right) = particles.split_at_mut(i + 1);
 
-        let (t1, v1, m1) = &mut left[i];
+        let (e1, t1, v1, m1, s1, c1) = &mut left[i];
         let right1 = t1.translation.x + t1.scale.x / 2.0;
 
         // O(1) at best; O(m/n) on average; O(n) at worst
-        for (t2, v2, m2) in right {
+        for (e2, t2, v2, m2, s2, c2) in right {
             let left2 = t2.translation.x - t2.scale.x / 2.0;
 
             if left2 > right1 {
                 break;
             }
 
+            if (*s1 && *s2) || welded(*c1, *c2) {
+                continue;
+            }
+
             let x1 = t1.translation.truncate();
             let x2 = t2.translation.truncate();
 
@@ -120,12 +505,15 @@ pub fn sweep_and_prune_collision_system(
 
             let distance = x1.distance(x2);
             if distance < r1 + r2 {
-                perform_collision(t1, v1, m1, t2, v2, m2);
+                stopwatch.respond(|| hooks.respond((*e1, t1, v1, m1, *c1), (*e2, t2, v2, m2, *c2)));
                 stats.num_collisions += 1;
+                contacts.pairs.push((*e1, *e2));
             }
         }
     }
 
+    stopwatch.stop(timings);
+
     // Final: O(n log n + m).
 }
 
@@ -150,20 +538,82 @@ impl SortedBallsCache {
             right_bound,
         });
     }
+
+    /// Sweep the cached order, calling `f` for every pair of balls whose x bounds
+    /// overlap once each is widened by `margin` on both sides.
+    ///
+    /// Widening every ball by the same amount keeps the sorted order valid, so this
+    /// finds balls within a given range of each other as cheaply as the collision sweep.
+    pub fn for_each_candidate_pair(&self, margin: f32, mut f: impl FnMut(Entity, Entity)) {
+        for (i, left_entity) in self.sorted_entities.iter().enumerate() {
+            let right_bound = left_entity.right_bound + margin;
+            for right_entity in &self.sorted_entities[i + 1..] {
+                if right_entity.left_bound - margin > right_bound {
+                    break;
+                }
+                f(left_entity.entity, right_entity.entity);
+            }
+        }
+    }
+
+    /// As `for_each_candidate_pair`, but if the world wraps around in x every `width`,
+    /// also call `f` for the pairs that only overlap around the back, between the
+    /// right-hand end of the order and the left-hand end moved one width along.
+    ///
+    /// Each pair is reported at most once, so callers should measure it by the nearest
+    /// periodic image.
+    pub fn for_each_candidate_pair_periodic(
+        &self,
+        margin: f32,
+        width: Option<f32>,
+        mut f: impl FnMut(Entity, Entity),
+    ) {
+        self.for_each_candidate_pair(margin, &mut f);
+        let Some(width) = width else {
+            return;
+        };
+
+        for (i, right_entity) in self.sorted_entities.iter().enumerate().rev() {
+            let right_bound = right_entity.right_bound + margin;
+            for left_entity in &self.sorted_entities[..i] {
+                if left_entity.left_bound + width - margin > right_bound {
+                    break;
+                }
+                // Already found by the direct sweep
+                if right_entity.left_bound - margin <= left_entity.right_bound + margin {
+                    continue;
+                }
+                f(right_entity.entity, left_entity.entity);
+            }
+        }
+    }
 }
 
 pub fn update_sorted_balls_cache(
     mut cache: ResMut<SortedBallsCache>,
     query: Query<(Entity, &Transform), With<Ball>>,
+    added: Query<(Entity, &Transform), Added<Ball>>,
+    timings: Option<ResMut<PhaseTimings>>,
 ) {
+    // Nothing to respond to here: it's all broadphase
+    let stopwatch = CollisionStopwatch::start(&timings);
+
     if !cache.sorted_entities.is_empty() {
-        // Update left and right bounds
-        for x in &mut cache.sorted_entities {
-            let (_, transform) = query.get(x.entity).unwrap();
+        // Update left and right bounds, dropping balls that have been despawned
+        cache.sorted_entities.retain_mut(|x| {
+            let Ok((_, transform)) = query.get(x.entity) else {
+                return false;
+            };
             let left_bound = transform.translation.x - transform.scale.x / 2.0;
             let right_bound = transform.translation.x + transform.scale.x / 2.0;
             x.left_bound = left_bound;
             x.right_bound = right_bound;
+            true
+        });
+
+        // Pick up balls spawned since the last update
+        for (entity, transform) in &added {
+            cache.add(entity, *transform);
         }
     } else {
         // Build cache
@@ -187,12 +637,17 @@ pub fn update_sorted_balls_cache(
     cache
         .sorted_entities
         .sort_by(|a, b| a.left_bound.partial_cmp(&b.left_bound).unwrap());
+
+    stopwatch.stop(timings);
 }
 
 pub fn sweep_and_prune_collision_system_with_cache(
-    mut query: Query<(&mut Transform, &mut Velocity, &Mass), With<Ball>>,
+    mut query: Query<CollisionQueryData, With<Ball>>,
     mut stats: ResMut<Stats>,
     mut cache: ResMut<SortedBallsCache>,
+    mut contacts: ResMut<Contacts>,
+    mut hooks: CollisionHooks,
+    timings: Option<ResMut<PhaseTimings>>,
 ) {
     // Sweep and prune collision detection
     // https://leanrada.com/notes/sweep-and-prune/
@@ -204,6 +659,9 @@ pub fn sweep_and_prune_collision_system_with_cache(
     //
     // Exploit temporal coherence by caching sorted order for fast re-sorting.
 
+    contacts.pairs.clear();
+    let mut stopwatch = CollisionStopwatch::start(&timings);
+
     // O(n + m)
     for i in 0..cache.sorted_entities.len() {
         let (left_split, right_split) = cache.sorted_entities.split_at_mut(i + 1);
@@ -219,10 +677,14 @@ pub fn sweep_and_prune_collision_system_with_cache(
                 break;
             }
 
-            let [(mut t1, mut v1, m1), (mut t2, mut v2, m2)] = query
+            let [(_, mut t1, mut v1, m1, s1, c1), (_, mut t2, mut v2, m2, s2, c2)] = query
                 .get_many_mut([left_entity.entity, right_entity.entity])
                 .unwrap();
 
+            if (s1 && s2) || welded(c1, c2) {
+                continue;
+            }
+
             let x1 = t1.translation.truncate();
             let x2 = t2.translation.truncate();
 
@@ -232,85 +694,21 @@ pub fn sweep_and_prune_collision_system_with_cache(
 
             let distance = x1.distance(x2);
             if distance < r1 + r2 {
-                perform_collision(&mut t1, &mut v1, m1, &mut t2, &mut v2, m2);
+                stopwatch.respond(|| {
+                    hooks.respond(
+                        (left_entity.entity, &mut t1, &mut v1, m1, c1),
+                        (right_entity.entity, &mut t2, &mut v2, m2, c2),
+                    )
+                });
                 stats.num_collisions += 1;
+                contacts
+                    .pairs
+                    .push((left_entity.entity, right_entity.entity));
             }
         }
     }
 
-    // Final: O(n log n + m).
-}
+    stopwatch.stop(timings);
 
-fn perform_collision(
-    t1: &mut Transform,
-    v1: &mut Velocity,
-    m1: &Mass,
-    t2: &mut Transform,
-    v2: &mut Velocity,
-    m2: &Mass,
-) {
-    // Use conservation of momentum to calculate new velocities
-    // https://en.wikipedia.org/wiki/Elastic_collision#Two-dimensional_collision_with_two_moving_objects
-
-    // let w1 = (2.0 * m2) / (m1 + m2) * (v1.0 - v2.0).dot(x1 - x2)
-    //     / (x1 - x2).length_squared()
-    //     * (x1 - x2);
-    // let w2 = (2.0 * m1) / (m1 + m2) * (v2.0 - v1.0).dot(x2 - x1)
-    //     / (x2 - x1).length_squared()
-    //     * (x2 - x1);
-
-    // Impulse model:
-    // https://en.wikipedia.org/wiki/Collision_response
-
-    let x1 = t1.translation.truncate();
-    let x2 = t2.translation.truncate();
-
-    // Use the x scaling as the diameter
-    let r1 = t1.scale.x / 2.0;
-    let r2 = t2.scale.x / 2.0;
-
-    let distance = x1.distance(x2);
-
-    let collision_normal = (x2 - x1).normalize();
-    // assert!(
-    //     !collision_normal.x.is_nan(),
-    //     "Found NaN in collision_normal.x"
-    // );
-    // assert!(
-    //     !collision_normal.y.is_nan(),
-    //     "Found NaN in collision_normal.y"
-    // );
-
-    // resolve overlap
-    let overlap = (r1 + r2) - distance;
-    t1.translation -= (overlap / 2.0) * collision_normal.extend(0.0);
-    t2.translation += (overlap / 2.0) * collision_normal.extend(0.0);
-
-    let relative_velocity = (v2.0 - v1.0).dot(collision_normal);
-    if relative_velocity > 0.0 {
-        // Already moving apart
-        return;
-    }
-
-    let e = 0.5; // Coefficient of restitution
-    let inverse_mass_sum = (1.0 / m1.0) + (1.0 / m2.0);
-    // assert!(m1.0 > 0.0, "m1 is zero");
-    // assert!(m2.0 > 0.0, "m1 is zero");
-    // assert!(!inverse_mass_sum.is_nan(), "Found NaN in inverse_mass_sum");
-
-    // Compute impulse
-    let impulse = -(1.0 + e) * relative_velocity / inverse_mass_sum;
-    let impulse_vector = impulse * collision_normal;
-    // assert!(!impulse_vector.x.is_nan(), "Found NaN in impulse_vector.x");
-    // assert!(!impulse_vector.y.is_nan(), "Found NaN in impulse_vector.y");
-
-    v1.0 -= impulse_vector / m1.0;
-    v2.0 += impulse_vector / m2.0;
-
-    // assert!(!v1.0.x.is_nan(), "Found NaN in v1.x");
-    // assert!(!v2.0.x.is_nan(), "Found NaN in v2.x");
-    // assert!(!t1.translation.x.is_nan(), "Found NaN in t1.x");
-    // assert!(!t1.translation.y.is_nan(), "Found NaN in t1.y");
-    // assert!(!t2.translation.x.is_nan(), "Found NaN in t2.x");
-    // assert!(!t2.translation.y.is_nan(), "Found NaN in t2.y");
+    // Final: O(n log n + m).
 }
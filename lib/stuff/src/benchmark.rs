@@ -1,7 +1,11 @@
-use crate::ball::Stats;
+use crate::ball::{Ball, PhysicsSet, Stats};
 use crate::fixed_frame_count_diagnostics_plugin::FixedFrameCountDiagnosticsPlugin;
+use bevy::app::{App, AppExit, FixedUpdate, Plugin};
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
-use bevy::prelude::{Real, Res, Resource, Time};
+use bevy::prelude::{EventWriter, IntoSystemConfigs, Local, Query, Res, ResMut, Resource, With};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 #[derive(Resource)]
 pub struct BenchmarkTargets {
@@ -10,63 +14,475 @@ pub struct BenchmarkTargets {
     pub frame_count: Option<f64>,
 }
 
+/// Where the benchmark report goes, and what it records about the run.
+#[derive(Resource, Default)]
+pub struct BenchmarkReportConfig {
+    /// JSON, or CSV if the extension is `.csv`. Only the summary is printed if not given
+    pub path: Option<PathBuf>,
+    pub seed: u64,
+}
+
+/// The physics phases timed during a benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Integrate,
+    /// The whole collision step; the broadphase is timed separately by the collision
+    /// systems and the rest is counted as narrowphase
+    Collide,
+    Warp,
+}
+
+/// Wall time spent in each physics phase, accumulated over the run.
+#[derive(Resource, Default)]
+pub struct PhaseTimings {
+    pub integrate: Duration,
+    pub collide: Duration,
+    /// Finding which balls collide, as reported by the collision systems: sorting
+    /// (including keeping `SortedBallsCache` up to date), sweeping or going through
+    /// every pair, and testing the candidates. See `CollisionStopwatch`.
+    pub broadphase: Duration,
+    pub warp: Duration,
+    last: Option<Instant>,
+}
+
+impl PhaseTimings {
+    fn start(&mut self) {
+        self.last = Some(Instant::now());
+    }
+
+    /// Add the time since the last start or lap to `phase`.
+    fn lap(&mut self, phase: Phase) {
+        let now = Instant::now();
+        if let Some(last) = self.last.replace(now) {
+            let elapsed = now - last;
+            match phase {
+                Phase::Integrate => self.integrate += elapsed,
+                Phase::Collide => self.collide += elapsed,
+                Phase::Warp => self.warp += elapsed,
+            }
+        }
+    }
+
+    /// Responding to the collisions found, and anything else in the collision step
+    /// such as pair forces
+    pub fn narrowphase(&self) -> Duration {
+        self.collide.saturating_sub(self.broadphase)
+    }
+}
+
+/// Times a collision system's search for collisions, leaving out the time spent
+/// responding to them, and adds it to `PhaseTimings::broadphase`. Every broadphase
+/// is timed this way, so they split the collision step at the same place.
+///
+/// Does nothing, not even reading the clock, unless `PhaseTimings` exists.
+pub struct CollisionStopwatch {
+    start: Option<Instant>,
+    responding: Duration,
+}
+
+impl CollisionStopwatch {
+    pub fn start(timings: &Option<ResMut<PhaseTimings>>) -> CollisionStopwatch {
+        CollisionStopwatch {
+            start: timings.is_some().then(Instant::now),
+            responding: Duration::ZERO,
+        }
+    }
+
+    /// Run `respond`, keeping its time out of the broadphase
+    pub fn respond<T>(&mut self, respond: impl FnOnce() -> T) -> T {
+        if self.start.is_none() {
+            return respond();
+        }
+        let start = Instant::now();
+        let result = respond();
+        self.responding += start.elapsed();
+        result
+    }
+
+    pub fn stop(self, timings: Option<ResMut<PhaseTimings>>) {
+        if let (Some(start), Some(mut timings)) = (self.start, timings) {
+            timings.broadphase += start.elapsed().saturating_sub(self.responding);
+        }
+    }
+}
+
+/// Times the physics phases by putting a stopwatch between the physics sets.
+#[derive(Default)]
+pub struct PhaseTimingsPlugin;
+
+impl Plugin for PhaseTimingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhaseTimings>().add_systems(
+            FixedUpdate,
+            (
+                (|mut t: ResMut<PhaseTimings>| t.start()).before(PhysicsSet::Integrate),
+                (|mut t: ResMut<PhaseTimings>| t.lap(Phase::Integrate))
+                    .after(PhysicsSet::Integrate)
+                    .before(PhysicsSet::Collide),
+                (|mut t: ResMut<PhaseTimings>| t.lap(Phase::Collide))
+                    .after(PhysicsSet::Collide)
+                    .before(PhysicsSet::Constrain),
+                (|mut t: ResMut<PhaseTimings>| t.start())
+                    .after(PhysicsSet::PostCollide)
+                    .before(PhysicsSet::Boundary),
+                (|mut t: ResMut<PhaseTimings>| t.lap(Phase::Warp)).after(PhysicsSet::Boundary),
+            ),
+        );
+    }
+}
+
+/// Everything measured by a benchmark run.
+#[derive(Debug, Clone, Default)]
+pub struct BenchmarkReport {
+    pub args: Vec<String>,
+    pub seed: u64,
+    pub num_balls: usize,
+    pub fixed_frames: u64,
+    pub frames: u64,
+    pub collisions: usize,
+    /// Seconds
+    pub wall_time: f64,
+    pub integrate: f64,
+    pub broadphase: f64,
+    pub narrowphase: f64,
+    pub warp: f64,
+}
+
+impl BenchmarkReport {
+    /// Zero if no time was recorded, rather than infinite
+    pub fn fixed_fps(&self) -> f64 {
+        self.per_second(self.fixed_frames)
+    }
+
+    /// Zero if no time was recorded, rather than infinite
+    pub fn fps(&self) -> f64 {
+        self.per_second(self.frames)
+    }
+
+    fn per_second(&self, count: u64) -> f64 {
+        if self.wall_time > 0.0 {
+            count as f64 / self.wall_time
+        } else {
+            0.0
+        }
+    }
+
+    pub const CSV_HEADER: &'static str = "args,seed,num_balls,fixed_frames,frames,collisions,\
+        wall_time,fixed_fps,fps,integrate,broadphase,narrowphase,warp";
+
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "\"{}\",{},{},{},{},{},{:.4},{:.2},{:.2},{:.6},{:.6},{:.6},{:.6}",
+            self.args.join(" ").replace('"', "\"\""),
+            self.seed,
+            self.num_balls,
+            self.fixed_frames,
+            self.frames,
+            self.collisions,
+            self.wall_time,
+            self.fixed_fps(),
+            self.fps(),
+            self.integrate,
+            self.broadphase,
+            self.narrowphase,
+            self.warp,
+        )
+    }
+
+    /// Read back a row written by `to_csv_row`.
+    pub fn from_csv_row(row: &str) -> Option<BenchmarkReport> {
+        // The quoted args come first, and may contain commas and doubled quotes
+        let quoted = row.strip_prefix('"')?;
+        let mut end = 0;
+        let mut chars = quoted.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if c == '"' {
+                if chars.peek().is_some_and(|(_, next)| *next == '"') {
+                    chars.next();
+                } else {
+                    end = i;
+                    break;
+                }
+            }
+        }
+        let args = quoted[..end].replace("\"\"", "\"");
+        let fields: Vec<&str> = quoted[end + 1..]
+            .trim_start_matches(',')
+            .split(',')
+            .collect();
+        if fields.len() != 12 {
+            return None;
+        }
+        Some(BenchmarkReport {
+            args: args.split(' ').map(String::from).collect(),
+            seed: fields[0].parse().ok()?,
+            num_balls: fields[1].parse().ok()?,
+            fixed_frames: fields[2].parse().ok()?,
+            frames: fields[3].parse().ok()?,
+            collisions: fields[4].parse().ok()?,
+            wall_time: fields[5].parse().ok()?,
+            // fixed_fps and fps are derived
+            integrate: fields[8].parse().ok()?,
+            broadphase: fields[9].parse().ok()?,
+            narrowphase: fields[10].parse().ok()?,
+            warp: fields[11].parse().ok()?,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(|arg| json_string(arg)).collect();
+        format!(
+            "{{\n  \"args\": [{}],\n  \"seed\": {},\n  \"num_balls\": {},\n  \
+             \"fixed_frames\": {},\n  \"frames\": {},\n  \"collisions\": {},\n  \
+             \"wall_time\": {},\n  \"fixed_fps\": {},\n  \"fps\": {},\n  \
+             \"phases\": {{\n    \"integrate\": {},\n    \"broadphase\": {},\n    \
+             \"narrowphase\": {},\n    \"warp\": {}\n  }}\n}}\n",
+            args.join(", "),
+            self.seed,
+            self.num_balls,
+            self.fixed_frames,
+            self.frames,
+            self.collisions,
+            self.wall_time,
+            self.fixed_fps(),
+            self.fps(),
+            self.integrate,
+            self.broadphase,
+            self.narrowphase,
+            self.warp,
+        )
+    }
+
+    /// Write as CSV if the path ends in `.csv`, otherwise as JSON.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let contents = if path.extension().is_some_and(|ext| ext == "csv") {
+            format!("{}\n{}\n", Self::CSV_HEADER, self.to_csv_row())
+        } else {
+            self.to_json()
+        };
+        std::fs::File::create(path)?.write_all(contents.as_bytes())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 // Use a variation of the DiagnosticsStore resource that records
 // the number of FixedUpdate frames, not Update frames, so that we can
 // benchmark the time to render a fixed number of physics frames.
+#[allow(clippy::too_many_arguments)]
 pub fn run_benchmark(
     diagnostics: Res<DiagnosticsStore>,
-    time: Res<Time<Real>>,
     targets: Res<BenchmarkTargets>,
+    config: Res<BenchmarkReportConfig>,
     stats: Res<Stats>,
+    timings: Option<Res<PhaseTimings>>,
+    balls: Query<(), With<Ball>>,
+    mut exit: EventWriter<AppExit>,
+    mut done: Local<bool>,
+    mut start: Local<Option<Instant>>,
 ) {
-    if let Some(fixed_frame_count) = diagnostics
-        .get(&FixedFrameCountDiagnosticsPlugin::FRAME_COUNT)
-        .and_then(|diagnostic| diagnostic.value())
-    {
-        if let Some(frame_count) = diagnostics
+    if *done {
+        // FixedUpdate can run again before the app gets round to exiting
+        return;
+    }
+    // Time<Real> is stepped by hand when headless, so keep our own clock
+    let duration = start
+        .get_or_insert_with(Instant::now)
+        .elapsed()
+        .as_secs_f64();
+
+    let (Some(fixed_frame_count), Some(frame_count)) = (
+        diagnostics
+            .get(&FixedFrameCountDiagnosticsPlugin::FRAME_COUNT)
+            .and_then(|diagnostic| diagnostic.value()),
+        diagnostics
             .get(&FrameTimeDiagnosticsPlugin::FRAME_COUNT)
-            .and_then(|diagnostic| diagnostic.value())
-        {
-            let duration = time.elapsed_seconds_f64();
-            let mut exit = false;
-
-            match *targets {
-                BenchmarkTargets {
-                    duration: Some(duration_target),
-                    fixed_frame_count: None,
-                    frame_count: None,
-                } => {
-                    if duration >= duration_target {
-                        exit = true;
-                    }
-                }
-                BenchmarkTargets {
-                    duration: None,
-                    fixed_frame_count: Some(frames_target),
-                    frame_count: None,
-                } => {
-                    if fixed_frame_count >= frames_target {
-                        exit = true;
-                    }
-                }
-                _ => {}
-            }
+            .and_then(|diagnostic| diagnostic.value()),
+    ) else {
+        return;
+    };
 
-            if exit {
-                println!(
-                    "Stopping the app at {:.2} seconds after {:.0} fixed frames, {:.2} fxd-fps, {:.2} fps",
-                    duration,
-                    fixed_frame_count,
-                    fixed_frame_count / duration,
-                    frame_count / duration,
-                );
-                println!("Number of collisions: {}", stats.num_collisions);
-                std::process::exit(0);
-            }
-        } else {
-            //println!("FrameTimeDiagnosticsPlugin::FRAME_COUNT not found");
+    let finished = match *targets {
+        BenchmarkTargets {
+            duration: Some(duration_target),
+            fixed_frame_count: None,
+            frame_count: None,
+        } => duration >= duration_target,
+        BenchmarkTargets {
+            duration: None,
+            fixed_frame_count: Some(frames_target),
+            frame_count: None,
+        } => fixed_frame_count >= frames_target,
+        _ => false,
+    };
+    if !finished {
+        return;
+    }
+    *done = true;
+
+    let seconds = |phase: fn(&PhaseTimings) -> Duration| {
+        timings.as_ref().map_or(0.0, |t| phase(t).as_secs_f64())
+    };
+    let report = BenchmarkReport {
+        args: std::env::args().collect(),
+        seed: config.seed,
+        num_balls: balls.iter().len(),
+        fixed_frames: fixed_frame_count as u64,
+        frames: frame_count as u64,
+        collisions: stats.num_collisions,
+        wall_time: duration,
+        integrate: seconds(|t| t.integrate),
+        broadphase: seconds(|t| t.broadphase),
+        narrowphase: seconds(PhaseTimings::narrowphase),
+        warp: seconds(|t| t.warp),
+    };
+
+    println!(
+        "Stopping the app at {:.2} seconds after {} fixed frames, {:.2} fxd-fps, {:.2} fps",
+        report.wall_time,
+        report.fixed_frames,
+        report.fixed_fps(),
+        report.fps(),
+    );
+    println!("Number of collisions: {}", report.collisions);
+    println!(
+        "Physics time: integrate {:.3}s, broadphase {:.3}s, narrowphase {:.3}s, warp {:.3}s",
+        report.integrate, report.broadphase, report.narrowphase, report.warp
+    );
+
+    if let Some(path) = &config.path {
+        if let Err(error) = report.write(path) {
+            eprintln!("Failed to write {}: {}", path.display(), error);
+        }
+    }
+
+    exit.send(AppExit::Success);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+
+    fn report() -> BenchmarkReport {
+        BenchmarkReport {
+            args: vec![
+                "many".into(),
+                "--output=\"runs,1.csv\"".into(),
+                "--seed".into(),
+                "7".into(),
+            ],
+            seed: 7,
+            num_balls: 500,
+            fixed_frames: 600,
+            frames: 300,
+            collisions: 1234,
+            wall_time: 2.5,
+            integrate: 0.125,
+            broadphase: 0.25,
+            narrowphase: 0.5,
+            warp: 0.0625,
         }
-    } else {
-        //println!("FixedFrameCountDiagnosticsPlugin::FRAME_COUNT not found");
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let report = report();
+        let row = report.to_csv_row();
+        assert!(row.starts_with("\"many --output=\"\"runs,1.csv\"\" --seed 7\","));
+
+        let read = BenchmarkReport::from_csv_row(&row).unwrap();
+        assert_eq!(read.args, report.args);
+        assert_eq!(read.seed, 7);
+        assert_eq!(read.num_balls, 500);
+        assert_eq!(read.fixed_frames, 600);
+        assert_eq!(read.frames, 300);
+        assert_eq!(read.collisions, 1234);
+        assert_eq!(read.wall_time, 2.5);
+        assert_eq!(read.integrate, 0.125);
+        assert_eq!(read.broadphase, 0.25);
+        assert_eq!(read.narrowphase, 0.5);
+        assert_eq!(read.warp, 0.0625);
+        assert_eq!(read.to_csv_row(), row);
+    }
+
+    #[test]
+    fn csv_header_matches_row() {
+        let header = BenchmarkReport::CSV_HEADER.split(',').count();
+        // The args contain a comma, but are quoted
+        let row = report().to_csv_row();
+        let unquoted = row.rsplit_once('"').unwrap().1;
+        assert_eq!(unquoted.split(',').count(), header);
+    }
+
+    #[test]
+    fn bad_csv_rows() {
+        assert!(BenchmarkReport::from_csv_row("").is_none());
+        assert!(BenchmarkReport::from_csv_row("many,1,2").is_none());
+        assert!(BenchmarkReport::from_csv_row("\"many\",1,2,3").is_none());
+        assert!(BenchmarkReport::from_csv_row("\"many\",x,1,1,1,1,1,1,1,1,1,1,1").is_none());
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("many"), "\"many\"");
+        assert_eq!(json_string("a \"b\""), "\"a \\\"b\\\"\"");
+        assert_eq!(json_string("c:\\d"), "\"c:\\\\d\"");
+        assert_eq!(json_string("e\nf\u{1}"), "\"e\\u000af\\u0001\"");
+    }
+
+    #[test]
+    fn zero_wall_time() {
+        let report = BenchmarkReport {
+            wall_time: 0.0,
+            ..report()
+        };
+        assert_eq!(report.fixed_fps(), 0.0);
+        assert_eq!(report.fps(), 0.0);
+
+        let json = report.to_json();
+        assert!(!json.contains("inf") && !json.contains("NaN"));
+        assert!(json.contains("\"fixed_fps\": 0,"));
+        assert!(json
+            .contains("\"args\": [\"many\", \"--output=\\\"runs,1.csv\\\"\", \"--seed\", \"7\"],"));
+    }
+
+    #[test]
+    fn stopwatch_leaves_out_responses() {
+        let mut world = World::new();
+        world.init_resource::<PhaseTimings>();
+        world.run_system_once(|timings: Option<ResMut<PhaseTimings>>| {
+            let mut stopwatch = CollisionStopwatch::start(&timings);
+            let responded = stopwatch.respond(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                true
+            });
+            assert!(responded);
+            stopwatch.stop(timings);
+        });
+        let timings = world.resource::<PhaseTimings>();
+        assert!(timings.broadphase < Duration::from_millis(25));
+
+        // Without timings there's nothing to add to, but responses still run
+        world.remove_resource::<PhaseTimings>();
+        world.run_system_once(|timings: Option<ResMut<PhaseTimings>>| {
+            let mut stopwatch = CollisionStopwatch::start(&timings);
+            assert!(stopwatch.start.is_none());
+            assert_eq!(stopwatch.respond(|| 3), 3);
+            stopwatch.stop(timings);
+        });
     }
 }
@@ -0,0 +1,81 @@
+use crate::ball::{apply_velocity_system, Mass, PhysicsSet, Velocity};
+use crate::random::random_normal;
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::Vec2;
+use bevy::prelude::{Component, IntoSystemConfigs, Query, Res, ResMut, Time, Transform, Without};
+use bevy_prng::ChaCha8Rng;
+use bevy_rand::prelude::GlobalEntropy;
+
+/// A body moved by overdamped Langevin (Brownian) dynamics rather than by
+/// `apply_velocity_system`, as if immersed in an implicit solvent at `temperature`.
+///
+/// Inertia is ignored: each step the body drifts at `F / friction` and jiggles by a
+/// random displacement with variance `2 D dt` per axis, where the diffusion
+/// coefficient `D = temperature / friction` (fluctuation-dissipation).
+///
+/// The force `F` is whatever changed the body's `Velocity` since the last step:
+/// force fields, pair potentials and collision impulses all act through it as usual.
+/// `Velocity` only ever holds the drift; the random part moves the body directly, so
+/// that nothing else mistakes it for motion the body has to answer for.
+#[derive(Component)]
+pub struct Brownian {
+    pub friction: f32,
+    pub temperature: f32,
+    /// Drift velocity as this integrator last left it
+    velocity: Vec2,
+}
+
+impl Brownian {
+    pub fn new(friction: f32, temperature: f32) -> Brownian {
+        Brownian {
+            friction,
+            temperature,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    pub fn diffusion_coefficient(&self) -> f32 {
+        self.temperature / self.friction
+    }
+}
+
+/// Moves `Brownian` bodies.
+#[derive(Default)]
+pub struct BrownianPlugin;
+
+impl Plugin for BrownianPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            brownian_system
+                .in_set(PhysicsSet::Integrate)
+                .after(apply_velocity_system),
+        );
+    }
+}
+
+pub fn brownian_system(
+    mut query: Query<(&mut Transform, &mut Velocity, &mut Brownian, &Mass), Without<Sleeping>>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    if dt <= 0.0 {
+        return;
+    }
+
+    for (mut transform, mut velocity, mut brownian, mass) in &mut query {
+        // Recover the net force from the impulses received since the last step
+        let force = mass.0 * (velocity.0 - brownian.velocity) / dt;
+        let drift = force / brownian.friction;
+
+        // Random displacement over the step
+        let sigma = (2.0 * brownian.diffusion_coefficient() * dt).sqrt();
+        let noise = sigma * Vec2::new(random_normal(&mut rng), random_normal(&mut rng));
+
+        velocity.0 = drift;
+        brownian.velocity = drift;
+        transform.translation += (drift * dt + noise).extend(0.0);
+    }
+}
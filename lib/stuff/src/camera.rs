@@ -0,0 +1,44 @@
+use crate::ball::WorldBounds;
+use bevy::app::{App, Plugin, PostUpdate, Update};
+use bevy::color::Color;
+use bevy::math::Vec2;
+use bevy::prelude::{DetectChanges, Gizmos, IntoSystemConfigs, Query, Res};
+use bevy::render::camera::{CameraUpdateSystem, OrthographicProjection, ScalingMode};
+
+/// Keeps the whole world in view whatever the size of the window. The world is
+/// scaled to fit, and if the window is a different shape the spare space either side
+/// is left empty, with the edge of the world outlined.
+///
+/// The physics only ever sees `WorldBounds`, so resizing the window changes the view
+/// and nothing else.
+#[derive(Default)]
+pub struct WorldCameraPlugin;
+
+impl Plugin for WorldCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            fit_camera_to_world_system.before(CameraUpdateSystem),
+        )
+        .add_systems(Update, draw_world_edge_system);
+    }
+}
+
+/// Fit new cameras, and all of them when the world changes size.
+pub fn fit_camera_to_world_system(
+    bounds: Res<WorldBounds>,
+    mut projections: Query<&mut OrthographicProjection>,
+) {
+    for mut projection in &mut projections {
+        if bounds.is_changed() || projection.is_added() {
+            projection.scaling_mode = ScalingMode::AutoMin {
+                min_width: bounds.width(),
+                min_height: bounds.height(),
+            };
+        }
+    }
+}
+
+pub fn draw_world_edge_system(mut gizmos: Gizmos, bounds: Res<WorldBounds>) {
+    gizmos.rect_2d(Vec2::ZERO, 0.0, bounds.size, Color::srgb(0.3, 0.3, 0.3));
+}
@@ -0,0 +1,515 @@
+use crate::ball::{apply_velocity_system, Ball, Mass, PhysicsSet, Velocity, WorldBounds};
+use crate::compound::CompoundPart;
+use crate::conserved_quantities_diagnostics_plugin::PotentialEnergy;
+use crate::my_color::charge_color;
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::ecs::query::Has;
+use bevy::log::warn;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, Added, Assets, ColorMaterial, Commands, Component, Entity, Handle,
+    IntoSystemConfigs, Local, Query, Res, ResMut, Resource, Time, Transform, Without,
+};
+use clap::ValueEnum;
+
+/// Electric charge carried by a ball.
+#[derive(Component)]
+pub struct Charge(pub f32);
+
+/// How the box edges are treated when summing Coulomb forces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CoulombBoundary {
+    /// Charges only see each other directly
+    Open,
+    /// Charges see the nearest periodic image of each other (minimum image convention),
+    /// matching the wrap-around of `ball_warp_system`
+    MinimumImage,
+    /// Ewald summation: a screened short-range sum over nearest images, plus a
+    /// long-range sum over reciprocal lattice vectors that accounts for every image
+    Ewald,
+}
+
+#[derive(Resource)]
+pub struct CoulombConfig {
+    /// Coulomb constant
+    pub k: f32,
+    /// Pairs further apart than this don't interact directly. For periodic boxes this
+    /// is limited to half the box size.
+    pub cutoff: f32,
+    pub boundary: CoulombBoundary,
+    /// Largest reciprocal lattice index used by `CoulombBoundary::Ewald`. By default
+    /// it's chosen from the box size so that the long-range sum stops where erfc has
+    /// fallen to the same ~2e-5 as the short-range sum does at the cutoff; fewer
+    /// vectors are quicker but leave the forces and energy correspondingly rougher.
+    pub k_max: Option<i32>,
+    /// Charge given to balls spawned without one, in pairs of opposite sign so the
+    /// system stays neutral; an odd ball out waits, uncharged, for a partner. Zero
+    /// leaves balls uncharged.
+    pub auto_charge: f32,
+}
+
+impl CoulombConfig {
+    pub fn new(k: f32) -> CoulombConfig {
+        CoulombConfig {
+            k,
+            cutoff: 100.0,
+            boundary: CoulombBoundary::Open,
+            k_max: None,
+            auto_charge: 0.0,
+        }
+    }
+}
+
+/// Pushes like charges apart and pulls opposite charges together.
+#[derive(Default)]
+pub struct CoulombPlugin;
+
+impl Plugin for CoulombPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                (auto_charge_system, charge_color_system).chain(),
+                coulomb_system.before(apply_velocity_system),
+            )
+                .chain()
+                .in_set(PhysicsSet::Integrate)
+                .run_if(resource_exists::<CoulombConfig>),
+        );
+    }
+}
+
+type UnchargedFilter = (Added<Ball>, Without<Charge>, Without<CompoundPart>);
+
+/// Give newly spawned balls a charge, if configured.
+///
+/// Charges are handed out in +/- pairs, so an odd number of balls doesn't leave the
+/// system with a net charge; the unpaired ball is charged when the next one arrives.
+pub fn auto_charge_system(
+    mut commands: Commands,
+    config: Res<CoulombConfig>,
+    added: Query<Entity, UnchargedFilter>,
+    mut unpaired: Local<Option<Entity>>,
+) {
+    if config.auto_charge == 0.0 {
+        return;
+    }
+    for entity in &added {
+        // The unpaired ball may have gone since, e.g. shattered or absorbed
+        match unpaired.take().and_then(|e| commands.get_entity(e)) {
+            Some(mut partner) => {
+                partner.insert(Charge(config.auto_charge));
+                commands.entity(entity).insert(Charge(-config.auto_charge));
+            }
+            None => *unpaired = Some(entity),
+        }
+    }
+}
+
+/// Colour newly charged balls by the sign of their charge.
+pub fn charge_color_system(
+    mut query: Query<(&Charge, &mut Handle<ColorMaterial>), Added<Charge>>,
+    all: Query<&Charge>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if query.is_empty() {
+        return;
+    }
+    let max_charge = all.iter().map(|q| q.0.abs()).fold(0.0, f32::max);
+    for (charge, mut material) in &mut query {
+        *material = materials.add(charge_color(charge.0, max_charge));
+    }
+}
+
+type ChargeQueryData = (
+    &'static mut Velocity,
+    &'static Mass,
+    &'static Transform,
+    &'static Charge,
+    Has<Sleeping>,
+);
+
+pub fn coulomb_system(
+    mut query: Query<ChargeQueryData, Without<CompoundPart>>,
+    config: Res<CoulombConfig>,
+    bounds: Res<WorldBounds>,
+    time: Res<Time>,
+    potential: Option<ResMut<PotentialEnergy>>,
+    mut warned: Local<bool>,
+) {
+    let box_size = bounds.size;
+
+    let charges: Vec<(Vec2, f32)> = query
+        .iter()
+        .map(|(_, _, transform, charge, _)| (transform.translation.truncate(), charge.0))
+        .collect();
+
+    if config.boundary == CoulombBoundary::Ewald && !*warned {
+        let net: f32 = charges.iter().map(|(_, q)| q).sum();
+        let scale: f32 = charges.iter().map(|(_, q)| q.abs()).sum();
+        if net.abs() > 1e-4 * scale {
+            warn!(
+                "Net charge {} in an Ewald sum: the k = 0 term is dropped, so the forces \
+                 and energy are those of a system neutralised by a uniform background",
+                net
+            );
+            *warned = true;
+        }
+    }
+
+    let mut forces = vec![Vec2::ZERO; charges.len()];
+    let periodic = config.boundary != CoulombBoundary::Open;
+    let cutoff = if periodic {
+        config.cutoff.min(box_size.min_element() / 2.0)
+    } else {
+        config.cutoff
+    };
+
+    // Short-range part
+    let alpha = match config.boundary {
+        CoulombBoundary::Ewald => EWALD_ACCURACY / cutoff,
+        _ => 0.0,
+    };
+    let mut energy = 0.0;
+    for_each_pair_within(&charges, cutoff, periodic.then_some(box_size), |i, j, r| {
+        // r points from j to i
+        let d = r.length();
+        if d == 0.0 {
+            return;
+        }
+        let qq = config.k * charges[i].1 * charges[j].1;
+        let (strength, phi) = if alpha > 0.0 {
+            let screened = erfc(alpha * d);
+            (
+                screened / (d * d)
+                    + 2.0 * alpha / std::f32::consts::PI.sqrt() * (-alpha * alpha * d * d).exp()
+                        / d,
+                screened / d,
+            )
+        } else {
+            (1.0 / (d * d), 1.0 / d)
+        };
+        let force = qq * strength * r / d;
+        forces[i] += force;
+        forces[j] -= force;
+        energy += qq * phi;
+    });
+
+    // Long-range part
+    if config.boundary == CoulombBoundary::Ewald {
+        energy += ewald_reciprocal(
+            &charges,
+            box_size,
+            alpha,
+            config.k_max,
+            config.k,
+            &mut forces,
+        );
+
+        // Each charge's own screening cloud
+        let sum_q_squared: f32 = charges.iter().map(|(_, q)| q * q).sum();
+        energy -= config.k * alpha / std::f32::consts::PI.sqrt() * sum_q_squared;
+    }
+
+    if let Some(mut potential) = potential {
+        potential.set("coulomb", energy);
+    }
+
+    let dt = time.delta_seconds();
+    for ((mut velocity, mass, _, _, sleeping), force) in query.iter_mut().zip(forces) {
+        if !sleeping {
+            velocity.0 += force / mass.0 * dt;
+        }
+    }
+}
+
+/// erfc(alpha * cutoff) for the Ewald splitting parameter; erfc(3) is about 2e-5,
+/// so the screened short-range sum can safely stop at the cutoff. The long-range sum
+/// stops at |k| = 2 alpha EWALD_ACCURACY for the same accuracy.
+const EWALD_ACCURACY: f32 = 3.0;
+
+/// Call `f(i, j, r)` for every pair closer than `cutoff`, where `r` is the shortest
+/// separation from `j` to `i`, wrapping around `box_size` if given.
+///
+/// Like sweep and prune, sort by x and only compare balls whose x positions are
+/// within the cutoff.
+fn for_each_pair_within(
+    points: &[(Vec2, f32)],
+    cutoff: f32,
+    box_size: Option<Vec2>,
+    mut f: impl FnMut(usize, usize, Vec2),
+) {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|&a, &b| points[a].0.x.partial_cmp(&points[b].0.x).unwrap());
+
+    let n = order.len();
+    for a in 0..n {
+        let i = order[a];
+        for step in 1..n {
+            let b = a + step;
+            if b >= n && box_size.is_none() {
+                break;
+            }
+            let j = order[b % n];
+
+            // Sweep on past the right-hand edge, into the next image of the box
+            let mut dx = points[j].0.x - points[i].0.x;
+            if b >= n {
+                dx += box_size.unwrap().x;
+            }
+            if dx > cutoff {
+                break;
+            }
+
+            let mut r = points[i].0 - points[j].0;
+            if let Some(size) = box_size {
+                r -= size * (r / size).round();
+            }
+            if r.length_squared() < cutoff * cutoff {
+                f(i, j, r);
+            }
+        }
+    }
+}
+
+/// Reciprocal-space part of the Ewald sum for point charges in a periodic 2D box,
+/// interacting through the 3D Coulomb potential.
+///
+/// The smooth long-range potential `erf(alpha r) / r` has the 2D Fourier transform
+/// `2 pi erfc(k / 2 alpha) / k`, so with the structure factor `S(k) = sum q e^(ik.r)`
+/// the energy is `(K / 2A) sum_k 2 pi erfc(k / 2 alpha) / k |S(k)|^2` and the force
+/// on charge i is `(K / A) sum_k 2 pi erfc(k / 2 alpha) / k * q_i k Im(e^(ik.r_i) S(k)*)`.
+///
+/// The sum runs over indices up to `k_max` in each direction, or if not given, over
+/// every wave vector shorter than `2 alpha EWALD_ACCURACY`.
+///
+/// Adds the forces to `forces` and returns the energy.
+fn ewald_reciprocal(
+    charges: &[(Vec2, f32)],
+    box_size: Vec2,
+    alpha: f32,
+    k_max: Option<i32>,
+    coulomb: f32,
+    forces: &mut [Vec2],
+) -> f32 {
+    let area = box_size.x * box_size.y;
+    let two_pi = 2.0 * std::f32::consts::PI;
+    let mut energy = 0.0;
+
+    let k_cutoff = 2.0 * alpha * EWALD_ACCURACY;
+    let (max_x, max_y) = match k_max {
+        Some(k_max) => (k_max, k_max),
+        // n = k L / 2 pi
+        None => (
+            (k_cutoff * box_size.x / two_pi).ceil() as i32,
+            (k_cutoff * box_size.y / two_pi).ceil() as i32,
+        ),
+    };
+
+    for nx in -max_x..=max_x {
+        for ny in -max_y..=max_y {
+            if nx == 0 && ny == 0 {
+                continue;
+            }
+            let k = two_pi * Vec2::new(nx as f32 / box_size.x, ny as f32 / box_size.y);
+            let k_length = k.length();
+            if k_max.is_none() && k_length > k_cutoff {
+                continue;
+            }
+            let weight = coulomb / area * two_pi * erfc(k_length / (2.0 * alpha)) / k_length;
+
+            let (mut s_cos, mut s_sin) = (0.0, 0.0);
+            for (x, q) in charges {
+                let phase = k.dot(*x);
+                s_cos += q * phase.cos();
+                s_sin += q * phase.sin();
+            }
+
+            energy += 0.5 * weight * (s_cos * s_cos + s_sin * s_sin);
+
+            for ((x, q), force) in charges.iter().zip(forces.iter_mut()) {
+                let phase = k.dot(*x);
+                *force += weight * q * (phase.sin() * s_cos - phase.cos() * s_sin) * k;
+            }
+        }
+    }
+
+    energy
+}
+
+/// Complementary error function, Abramowitz & Stegun 7.1.26 (error below 1.5e-7)
+fn erfc(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t
+        * (0.254_829_6
+            + t * (-0.284_496_74 + t * (1.421_413_8 + t * (-1.453_152_1 + t * 1.061_405_4))));
+    let y = polynomial * (-x * x).exp();
+    if x >= 0.0 {
+        y
+    } else {
+        2.0 - y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+    use std::time::Duration;
+
+    #[test]
+    fn erfc_known_values() {
+        for (x, expected) in [
+            (0.0, 1.0),
+            (0.5, 0.479_500_1),
+            (1.0, 0.157_299_2),
+            (2.0, 0.004_677_735),
+            (3.0, 0.000_022_090_5),
+            (-1.0, 1.842_700_8),
+        ] {
+            assert!((erfc(x) - expected).abs() < 2e-7, "erfc({})", x);
+        }
+    }
+
+    #[test]
+    fn auto_charges_stay_neutral() {
+        let mut world = World::new();
+        world.insert_resource(CoulombConfig {
+            auto_charge: 2.0,
+            ..CoulombConfig::new(1.0)
+        });
+        let system = world.register_system(auto_charge_system);
+        let net_charge = |world: &mut World| {
+            let charges: Vec<f32> = world.query::<&Charge>().iter(world).map(|q| q.0).collect();
+            (charges.len(), charges.iter().sum::<f32>())
+        };
+
+        world.spawn_batch([Ball, Ball, Ball]);
+        world.run_system(system).unwrap();
+        assert_eq!(net_charge(&mut world), (2, 0.0));
+
+        world.spawn(Ball);
+        world.run_system(system).unwrap();
+        assert_eq!(net_charge(&mut world), (4, 0.0));
+    }
+
+    const BOX: f32 = 20.0;
+
+    /// Two opposite dipoles, so the box has neither net charge nor dipole moment and
+    /// a plain sum over images converges quickly
+    fn neutral_charges() -> Vec<(Vec2, f32)> {
+        vec![
+            (Vec2::new(3.0, 4.0), 1.0),
+            (Vec2::new(5.5, 7.0), -1.0),
+            (Vec2::new(14.0, 12.5), -1.0),
+            (Vec2::new(16.5, 15.5), 1.0),
+        ]
+    }
+
+    /// Forces and energy summed directly over every image within `images` boxes
+    fn image_sum(charges: &[(Vec2, f32)], images: i32) -> (Vec<Vec2>, f32) {
+        let mut forces = vec![Vec2::ZERO; charges.len()];
+        let mut energy = 0.0f64;
+        for (i, (x1, q1)) in charges.iter().enumerate() {
+            let mut force = [0.0f64; 2];
+            for (j, (x2, q2)) in charges.iter().enumerate() {
+                for nx in -images..=images {
+                    for ny in -images..=images {
+                        if (i == j && nx == 0 && ny == 0) || nx * nx + ny * ny > images * images {
+                            continue;
+                        }
+                        let r = [
+                            (x1.x - x2.x) as f64 + (nx as f32 * BOX) as f64,
+                            (x1.y - x2.y) as f64 + (ny as f32 * BOX) as f64,
+                        ];
+                        let d = (r[0] * r[0] + r[1] * r[1]).sqrt();
+                        let qq = (q1 * q2) as f64;
+                        force[0] += qq * r[0] / (d * d * d);
+                        force[1] += qq * r[1] / (d * d * d);
+                        energy += 0.5 * qq / d;
+                    }
+                }
+            }
+            forces[i] = Vec2::new(force[0] as f32, force[1] as f32);
+        }
+        (forces, energy as f32)
+    }
+
+    /// Forces and energy from one step of `coulomb_system`
+    fn coulomb_forces(charges: &[(Vec2, f32)], boundary: CoulombBoundary) -> (Vec<Vec2>, f32) {
+        let mut world = World::new();
+        world.insert_resource(CoulombConfig {
+            cutoff: BOX / 2.0,
+            boundary,
+            ..CoulombConfig::new(1.0)
+        });
+        world.insert_resource(WorldBounds {
+            size: Vec2::splat(BOX),
+        });
+        world.init_resource::<PotentialEnergy>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+
+        let entities: Vec<Entity> = charges
+            .iter()
+            .map(|(x, q)| {
+                world
+                    .spawn((
+                        Transform::from_translation(x.extend(0.0)),
+                        Velocity(Vec2::ZERO),
+                        Mass(1.0),
+                        Charge(*q),
+                    ))
+                    .id()
+            })
+            .collect();
+        world.run_system_once(coulomb_system);
+
+        // Unit mass for one second: the velocity is the force
+        let forces = entities
+            .iter()
+            .map(|e| world.get::<Velocity>(*e).unwrap().0)
+            .collect();
+        let energy = world.resource::<PotentialEnergy>().get("coulomb").unwrap();
+        (forces, energy)
+    }
+
+    #[test]
+    fn ewald_matches_image_sum() {
+        let charges = neutral_charges();
+        let (expected_forces, expected_energy) = image_sum(&charges, 100);
+        let (forces, energy) = coulomb_forces(&charges, CoulombBoundary::Ewald);
+
+        for (force, expected) in forces.iter().zip(&expected_forces) {
+            assert!(
+                (*force - *expected).length() < 1e-3 * expected.length(),
+                "{} != {}",
+                force,
+                expected
+            );
+        }
+        assert!(
+            (energy - expected_energy).abs() < 1e-3 * expected_energy.abs(),
+            "{} != {}",
+            energy,
+            expected_energy
+        );
+    }
+
+    #[test]
+    fn minimum_image_misses_the_far_images() {
+        // Nearest images alone are only roughly right; Ewald is what fixes that
+        let charges = neutral_charges();
+        let (expected_forces, _) = image_sum(&charges, 100);
+        let (forces, _) = coulomb_forces(&charges, CoulombBoundary::MinimumImage);
+        let worst = forces
+            .iter()
+            .zip(&expected_forces)
+            .map(|(f, e)| (*f - *e).length() / e.length())
+            .fold(0.0, f32::max);
+        assert!(worst > 1e-3);
+    }
+}
@@ -1,4 +1,9 @@
-use clap::{Args, Parser, Subcommand};
+use crate::ball::WorldBounds;
+use crate::charge::CoulombBoundary;
+use crate::thermostat::ThermostatKind;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Cli {
@@ -9,6 +14,30 @@ pub struct Cli {
     pub command: Option<Command>,
 }
 
+impl Cli {
+    /// The world bounds from the command line, falling back to `default` for any
+    /// dimension not given.
+    pub fn world_bounds(&self, default: WorldBounds) -> WorldBounds {
+        WorldBounds::new(
+            self.global_opts.world_width.unwrap_or(default.width()),
+            self.global_opts.world_height.unwrap_or(default.height()),
+        )
+    }
+
+    /// For bins without a `--num-balls` option for `sweep` to vary: exit with a usage
+    /// error if `sweep` was asked for, rather than have every benchmark fail.
+    pub fn reject_sweep<C: CommandFactory>(&self) {
+        if let Some(Command::Sweep { .. }) = self.command {
+            C::command()
+                .error(
+                    ErrorKind::InvalidSubcommand,
+                    "sweep needs a bin with a --num-balls option, such as many or smash",
+                )
+                .exit();
+        }
+    }
+}
+
 #[derive(Debug, Args)]
 pub struct GlobalOpts {
     // /// Verbosity level (can be specified multiple times)
@@ -25,6 +54,228 @@ pub struct GlobalOpts {
 
     #[clap(long, short, global = true, default_value_t = 0)]
     pub(crate) seed: u64,
+
+    /// Put balls to sleep when their contact island comes to rest
+    #[clap(long, global = true)]
+    pub(crate) sleep: bool,
+
+    /// Merge colliding balls that approach each other slower than STICKINESS
+    #[clap(long, global = true, value_name = "STICKINESS")]
+    pub(crate) accrete: Option<f32>,
+
+    /// Shatter balls in collisions with an impact energy above ENERGY
+    #[clap(long, global = true, value_name = "ENERGY")]
+    pub(crate) fragment: Option<f32>,
+
+    /// How to find pairs of balls that might be touching
+    #[clap(long, global = true, value_enum, default_value_t = Broadphase::SapCached)]
+    pub(crate) broadphase: Broadphase,
+
+    /// How touching balls push each other apart
+    #[clap(long, global = true, value_enum, default_value_t = ContactModelKind::Impulse)]
+    pub(crate) contact_model: ContactModelKind,
+
+    /// Downward acceleration due to gravity (toggle with G)
+    #[clap(
+        long,
+        global = true,
+        value_name = "ACCELERATION",
+        default_value_t = 0.0
+    )]
+    pub(crate) gravity: f32,
+
+    /// Linear (Stokes) drag coefficient
+    #[clap(long, global = true, value_name = "COEFFICIENT", default_value_t = 0.0)]
+    pub(crate) drag: f32,
+
+    /// Quadratic (air) drag coefficient
+    #[clap(long, global = true, value_name = "COEFFICIENT", default_value_t = 0.0)]
+    pub(crate) air_drag: f32,
+
+    /// Mutual gravitation between all balls, with gravitational constant G
+    #[clap(long, global = true, value_name = "G")]
+    pub(crate) nbody: Option<f32>,
+
+    /// Softening length for mutual gravitation
+    #[clap(long, global = true, default_value_t = 5.0)]
+    pub(crate) softening: f32,
+
+    /// Barnes-Hut opening angle for mutual gravitation
+    #[clap(long, global = true, value_name = "THETA", default_value_t = 0.5)]
+    pub(crate) opening_angle: f32,
+
+    /// Sum mutual gravitation over every pair instead of using Barnes-Hut
+    #[clap(long, global = true)]
+    pub(crate) direct_sum: bool,
+
+    /// Coulomb forces between charged balls, with Coulomb constant K
+    #[clap(long, global = true, value_name = "K")]
+    pub(crate) coulomb: Option<f32>,
+
+    /// Give every ball a charge of +/-CHARGE, in opposite pairs so the system is neutral
+    #[clap(long, global = true, default_value_t = 0.0)]
+    pub(crate) charge: f32,
+
+    /// Cutoff distance for Coulomb forces
+    #[clap(long, global = true, default_value_t = 100.0)]
+    pub(crate) cutoff: f32,
+
+    /// How Coulomb forces treat the edges of the box
+    #[clap(long, global = true, value_enum, default_value_t = CoulombBoundary::Open)]
+    pub(crate) coulomb_boundary: CoulombBoundary,
+
+    /// Largest reciprocal lattice index for Ewald summation, instead of one chosen
+    /// from the box size to match the accuracy of the cutoff
+    #[clap(long, global = true, value_name = "INDEX")]
+    pub(crate) ewald_k_max: Option<i32>,
+
+    /// Replace hard-sphere collisions with a Lennard-Jones potential of well depth EPSILON
+    #[clap(long, global = true, value_name = "EPSILON")]
+    pub(crate) lennard_jones: Option<f32>,
+
+    /// Lennard-Jones cutoff, in units of sigma
+    #[clap(long, global = true, value_name = "SIGMAS", default_value_t = 2.5)]
+    pub(crate) lj_cutoff: f32,
+
+    /// Neighbour list skin distance for Lennard-Jones
+    #[clap(long, global = true, default_value_t = 5.0)]
+    pub(crate) skin: f32,
+
+    /// Log energy and momentum every second
+    #[clap(long, global = true)]
+    pub(crate) conservation: bool,
+
+    /// Histogram ball speeds every FRAMES fixed frames, fitting a Maxwell-Boltzmann distribution
+    #[clap(long, global = true, value_name = "FRAMES")]
+    pub(crate) speed_histogram: Option<u32>,
+
+    /// Write the final speed histogram to this CSV file
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) speed_histogram_output: Option<PathBuf>,
+
+    /// Count collisions and free paths for each ball
+    #[clap(long, global = true)]
+    pub(crate) collision_stats: bool,
+
+    /// Write the per-ball collision counters to this CSV file at the end of the run
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) collision_stats_output: Option<PathBuf>,
+
+    /// Measure diffusion from the balls' unwrapped trajectories, sampled every FRAMES fixed frames
+    #[clap(long, global = true, value_name = "FRAMES")]
+    pub(crate) diffusion: Option<u32>,
+
+    /// Longest lag of the MSD and VACF, in samples
+    #[clap(long, global = true, default_value_t = 100)]
+    pub(crate) max_lag: usize,
+
+    /// Write the MSD and VACF to this CSV file at the end of the run
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) diffusion_output: Option<PathBuf>,
+
+    /// Measure the pair correlation function g(r) every FRAMES fixed frames
+    #[clap(long, global = true, value_name = "FRAMES")]
+    pub(crate) rdf: Option<u32>,
+
+    /// Number of samples to average g(r) and S(k) over
+    #[clap(long, global = true, default_value_t = 10, value_name = "SAMPLES")]
+    pub(crate) rdf_window: usize,
+
+    /// Largest separation for g(r), in mean ball diameters
+    #[clap(long, global = true, default_value_t = 5.0, value_name = "DIAMETERS")]
+    pub(crate) rdf_range: f32,
+
+    /// Also compute the structure factor S(k), up to MODES multiples of 2 pi / L
+    #[clap(long, global = true, value_name = "MODES")]
+    pub(crate) structure_factor: Option<i32>,
+
+    /// Write g(r) to this CSV file at the end of the run
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) rdf_output: Option<PathBuf>,
+
+    /// Write S(k) to this CSV file at the end of the run
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) structure_factor_output: Option<PathBuf>,
+
+    /// Label clusters of touching or bonded balls every FRAMES fixed frames
+    #[clap(long, global = true, value_name = "FRAMES")]
+    pub(crate) clusters: Option<u32>,
+
+    /// Largest gap between two balls that still counts as touching
+    #[clap(long, global = true, default_value_t = 1.0)]
+    pub(crate) cluster_tolerance: f32,
+
+    /// Colour balls by cluster
+    #[clap(long, global = true)]
+    pub(crate) color_clusters: bool,
+
+    /// Write the final cluster-size distribution to this CSV file
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) clusters_output: Option<PathBuf>,
+
+    /// Measure area fraction, coordination number and Voronoi cells every FRAMES fixed frames
+    #[clap(long, global = true, value_name = "FRAMES")]
+    pub(crate) packing: Option<u32>,
+
+    /// Largest gap between two balls that still counts as a contact
+    #[clap(long, global = true, default_value_t = 0.5)]
+    pub(crate) contact_tolerance: f32,
+
+    /// Write the per-ball packing measurements to this CSV file at the end of the run
+    #[clap(long, global = true, value_name = "PATH")]
+    pub(crate) packing_output: Option<PathBuf>,
+
+    /// Run without a window or renderer, stepping the physics as fast as possible
+    #[clap(long, global = true)]
+    pub(crate) headless: bool,
+
+    /// Width of the world the balls live in, instead of the bin's default
+    #[clap(long, global = true, value_name = "WIDTH")]
+    pub(crate) world_width: Option<f32>,
+
+    /// Height of the world the balls live in, instead of the bin's default
+    #[clap(long, global = true, value_name = "HEIGHT")]
+    pub(crate) world_height: Option<f32>,
+
+    /// Bounce balls off the edges of the world instead of wrapping them around
+    #[clap(long, global = true)]
+    pub(crate) walls: bool,
+
+    /// Measure wall and virial pressure, averaged over STEPS fixed steps
+    #[clap(long, global = true, value_name = "STEPS")]
+    pub(crate) pressure: Option<usize>,
+
+    /// Hold the balls at a constant temperature
+    #[clap(long, global = true, value_enum)]
+    pub(crate) thermostat: Option<ThermostatKind>,
+
+    /// Thermostat target temperature (mean kinetic energy per ball)
+    #[clap(long, global = true, default_value_t = 1000.0)]
+    pub(crate) temperature: f32,
+
+    /// Thermostat coupling time constant, in seconds
+    #[clap(long, global = true, value_name = "SECONDS", default_value_t = 1.0)]
+    pub(crate) thermostat_time: f32,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ContactModelKind {
+    /// Hard spheres with a coefficient of restitution of 0.5
+    Impulse,
+    /// Hard spheres that lose no energy
+    Elastic,
+    /// Soft spheres with a Hertzian spring-dashpot (DEM)
+    Hertzian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Broadphase {
+    /// Test every pair of balls
+    Naive,
+    /// Sweep and prune, sorting from scratch every step
+    Sap,
+    /// Sweep and prune, keeping the sorted order between steps
+    SapCached,
 }
 
 #[derive(Debug, Subcommand)]
@@ -46,8 +297,62 @@ pub enum Command {
             help = "Run to number of fixed update frames"
         )]
         frames: Option<f64>,
+
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Write a report with per-phase timings, as CSV if PATH ends in .csv, otherwise JSON"
+        )]
+        report: Option<PathBuf>,
         // (can #[clap(flatten)] other argument structs here)
     },
+    /// Benchmark every combination of ball count, broadphase and physics rate.
+    ///
+    /// Each combination runs as its own benchmark process, passing the ball count to
+    /// the binary's --num-balls option.
+    Sweep {
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_values_t = [1000, 2500, 5000, 10000],
+            help = "Ball counts to try"
+        )]
+        balls: Vec<usize>,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_enum,
+            default_values_t = [Broadphase::Naive, Broadphase::SapCached],
+            help = "Broadphases to try"
+        )]
+        broadphases: Vec<Broadphase>,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_values_t = [128.0],
+            help = "Physics rates (Hz) to try"
+        )]
+        rates: Vec<f64>,
+
+        #[arg(
+            short = 'f',
+            long = "frames",
+            value_name = "FRAMES",
+            default_value_t = 500.0,
+            help = "Run each combination to this number of fixed update frames"
+        )]
+        frames: f64,
+
+        #[arg(
+            short = 'o',
+            long,
+            value_name = "PATH",
+            help = "Also write the comparison table as CSV"
+        )]
+        output: Option<PathBuf>,
+    },
     // ...other commands (can #[clap(flatten)] other enum variants here)
 }
 
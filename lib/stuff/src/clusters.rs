@@ -0,0 +1,329 @@
+use crate::ball::{separation, Ball, Boundary, PhysicsSet, SortedBallsCache, WorldBounds};
+use crate::compound::CompoundPart;
+use crate::constraint::Bonds;
+use crate::setup::Headless;
+use crate::union_find::UnionFind;
+use bevy::app::{App, AppExit, FixedUpdate, Last, Plugin, Update};
+use bevy::asset::{Assets, Handle};
+use bevy::color::Color;
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::{
+    resource_exists, Commands, Component, Entity, EventReader, IntoSystemConfigs, Local, Query,
+    Res, ResMut, Resource, Transform, With,
+};
+use bevy::sprite::ColorMaterial;
+use bevy::utils::HashMap;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Resource)]
+pub struct ClusterConfig {
+    /// Label the clusters every this many fixed frames
+    pub every: u32,
+    /// Balls whose surfaces are closer than this count as touching
+    pub tolerance: f32,
+    /// Colour balls by the cluster they belong to
+    pub recolor: bool,
+    /// Where to write the final cluster-size distribution as CSV
+    pub output: Option<PathBuf>,
+}
+
+impl ClusterConfig {
+    pub fn new(every: u32) -> ClusterConfig {
+        ClusterConfig {
+            every,
+            tolerance: 1.0,
+            recolor: false,
+            output: None,
+        }
+    }
+}
+
+/// Which cluster a ball was in at the last labelling. Clusters are numbered from
+/// the largest down, so the label of a ball says how big its cluster is relative to
+/// the others.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterLabel(pub usize);
+
+/// Result of the last labelling.
+#[derive(Resource, Default)]
+pub struct Clusters {
+    /// Size of each cluster, largest first
+    pub sizes: Vec<usize>,
+}
+
+impl Clusters {
+    /// Number of clusters of each size
+    pub fn size_distribution(&self) -> BTreeMap<usize, usize> {
+        let mut distribution = BTreeMap::new();
+        for size in &self.sizes {
+            *distribution.entry(*size).or_default() += 1;
+        }
+        distribution
+    }
+}
+
+/// Groups balls that touch, or are bonded by joints, springs, ropes or compound
+/// bodies, into connected clusters.
+///
+/// Touching balls are found with the sorted order of `SortedBallsCache`, which
+/// `add_ball_physics_systems` keeps up to date whenever this is on. In a wrapping box
+/// they may touch around the back.
+#[derive(Default)]
+pub struct ClusterPlugin;
+
+impl Plugin for ClusterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Clusters>()
+            .register_diagnostic(Diagnostic::new(Self::NUM_CLUSTERS))
+            .register_diagnostic(Diagnostic::new(Self::LARGEST_CLUSTER))
+            .add_systems(
+                FixedUpdate,
+                cluster_system
+                    .in_set(PhysicsSet::PostCollide)
+                    .run_if(resource_exists::<ClusterConfig>),
+            )
+            .add_systems(Update, cluster_color_system.run_if(recolor_clusters))
+            .add_systems(
+                Last,
+                report_clusters_system.run_if(resource_exists::<ClusterConfig>),
+            );
+    }
+}
+
+impl ClusterPlugin {
+    pub const NUM_CLUSTERS: DiagnosticPath = DiagnosticPath::const_new("clusters/count");
+    /// Number of balls in the largest cluster
+    pub const LARGEST_CLUSTER: DiagnosticPath = DiagnosticPath::const_new("clusters/largest");
+}
+
+type ClusterQueryData = (
+    Entity,
+    &'static Transform,
+    Option<&'static CompoundPart>,
+    Option<&'static mut ClusterLabel>,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub fn cluster_system(
+    mut diagnostics: Diagnostics,
+    mut balls: Query<ClusterQueryData, With<Ball>>,
+    bonds: Bonds,
+    cache: Res<SortedBallsCache>,
+    config: Res<ClusterConfig>,
+    boundary: Res<Boundary>,
+    bounds: Res<WorldBounds>,
+    mut clusters: ResMut<Clusters>,
+    mut commands: Commands,
+    mut frames: Local<u32>,
+) {
+    *frames += 1;
+    if *frames < config.every {
+        return;
+    }
+    *frames = 0;
+
+    let index: HashMap<Entity, usize> = balls
+        .iter()
+        .enumerate()
+        .map(|(i, (entity, ..))| (entity, i))
+        .collect();
+    let mut sets = UnionFind::new(index.len());
+
+    // Touching, measured to the nearest image if the box wraps
+    let box_size = boundary.periodic_box(&bounds);
+    let margin = config.tolerance / 2.0;
+    cache.for_each_candidate_pair_periodic(margin, box_size.map(|size| size.x), |a, b| {
+        let (Ok((_, ta, ..)), Ok((_, tb, ..))) = (balls.get(a), balls.get(b)) else {
+            return;
+        };
+        let distance = separation(
+            ta.translation.truncate(),
+            tb.translation.truncate(),
+            box_size,
+        )
+        .length();
+        let gap = distance - (ta.scale.x + tb.scale.x) / 2.0;
+        if gap < config.tolerance {
+            sets.union(index[&a], index[&b]);
+        }
+    });
+
+    // Bonded, or welded into a compound body through its first part
+    let mut first_part: HashMap<Entity, Entity> = HashMap::new();
+    let parts: Vec<(Entity, Entity)> = balls
+        .iter()
+        .filter_map(|(entity, _, part, _)| Some((entity, part?.body)))
+        .map(|(entity, body)| (*first_part.entry(body).or_insert(entity), entity))
+        .collect();
+    for (a, b) in bonds.pairs().chain(parts) {
+        if let (Some(a), Some(b)) = (index.get(&a), index.get(&b)) {
+            sets.union(*a, *b);
+        }
+    }
+
+    // Number the clusters from the largest down
+    let mut roots: Vec<usize> = (0..index.len()).filter(|i| sets.find(*i) == *i).collect();
+    roots.sort_by_key(|root| std::cmp::Reverse(sets.size(*root)));
+    let labels: HashMap<usize, usize> = roots
+        .iter()
+        .enumerate()
+        .map(|(label, root)| (*root, label))
+        .collect();
+    clusters.sizes = roots.iter().map(|root| sets.size(*root)).collect();
+
+    for (entity, _, _, label) in &mut balls {
+        let new_label = ClusterLabel(labels[&sets.find(index[&entity])]);
+        match label {
+            Some(mut label) => {
+                if *label != new_label {
+                    *label = new_label;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(new_label);
+            }
+        }
+    }
+
+    let num_clusters = clusters.sizes.len();
+    diagnostics.add_measurement(&ClusterPlugin::NUM_CLUSTERS, || num_clusters as f64);
+    if let Some(largest) = clusters.sizes.first() {
+        diagnostics.add_measurement(&ClusterPlugin::LARGEST_CLUSTER, || *largest as f64);
+    }
+}
+
+/// Colouring is only worth doing if there's something to see it with.
+fn recolor_clusters(config: Option<Res<ClusterConfig>>, headless: Option<Res<Headless>>) -> bool {
+    headless.is_none() && config.is_some_and(|config| config.recolor)
+}
+
+const NUM_CLUSTER_COLORS: usize = 12;
+
+/// Colour each ball by its cluster, cycling through a fixed palette from the largest
+/// cluster down. Balls on their own are grey.
+pub fn cluster_color_system(
+    mut balls: Query<(&ClusterLabel, &mut Handle<ColorMaterial>)>,
+    clusters: Res<Clusters>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut palette: Local<Vec<Handle<ColorMaterial>>>,
+) {
+    if palette.is_empty() {
+        // Spread the hues by the golden angle, so that neighbouring labels differ
+        *palette = (0..NUM_CLUSTER_COLORS)
+            .map(|i| materials.add(Color::hsl(i as f32 * 137.5 % 360.0, 0.8, 0.55)))
+            .collect();
+        palette.push(materials.add(Color::srgb(0.4, 0.4, 0.4)));
+    }
+
+    for (label, mut material) in &mut balls {
+        let color = if clusters.sizes.get(label.0).is_some_and(|size| *size > 1) {
+            &palette[label.0 % NUM_CLUSTER_COLORS]
+        } else {
+            &palette[NUM_CLUSTER_COLORS]
+        };
+        if *material != *color {
+            *material = color.clone();
+        }
+    }
+}
+
+/// Print the cluster-size distribution, and write it out, when the app exits.
+pub fn report_clusters_system(
+    mut exit: EventReader<AppExit>,
+    config: Res<ClusterConfig>,
+    clusters: Res<Clusters>,
+) {
+    if exit.read().next().is_none() || clusters.sizes.is_empty() {
+        return;
+    }
+
+    let distribution = clusters.size_distribution();
+    println!(
+        "{} clusters, largest {} balls",
+        clusters.sizes.len(),
+        clusters.sizes[0]
+    );
+    let mut csv = String::from("size,count\n");
+    for (size, count) in &distribution {
+        csv += &format!("{},{}\n", size, count);
+    }
+
+    match &config.output {
+        Some(path) => {
+            if let Err(error) =
+                std::fs::File::create(path).and_then(|mut file| file.write_all(csv.as_bytes()))
+            {
+                eprintln!("Failed to write {}: {}", path.display(), error);
+            }
+        }
+        None => print!("{}", csv),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ball::update_sorted_balls_cache;
+    use crate::constraint::DistanceJoint;
+    use bevy::diagnostic::DiagnosticsStore;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::math::Vec2;
+    use bevy::prelude::World;
+
+    /// A 100 x 100 box of balls of diameter 10
+    fn world(boundary: Boundary) -> World {
+        let mut world = World::new();
+        world.insert_resource(ClusterConfig::new(1));
+        world.insert_resource(boundary);
+        world.insert_resource(WorldBounds {
+            size: Vec2::splat(100.0),
+        });
+        world.init_resource::<Clusters>();
+        world.init_resource::<SortedBallsCache>();
+        world.init_resource::<DiagnosticsStore>();
+        world
+    }
+
+    fn spawn_ball(world: &mut World, x: f32, y: f32) -> Entity {
+        world
+            .spawn((
+                Ball,
+                Transform::from_xyz(x, y, 0.0).with_scale(Vec2::splat(10.0).extend(1.0)),
+            ))
+            .id()
+    }
+
+    fn cluster_sizes(world: &mut World) -> Vec<usize> {
+        world.run_system_once(update_sorted_balls_cache);
+        world.run_system_once(cluster_system);
+        world.resource::<Clusters>().sizes.clone()
+    }
+
+    #[test]
+    fn touching_around_the_wrap() {
+        for (boundary, sizes) in [
+            (Boundary::Wrap, vec![3, 1]),
+            (Boundary::Reflect, vec![2, 1, 1]),
+        ] {
+            let mut world = world(boundary);
+            // Touching directly, touching the first around the back in x, and alone
+            spawn_ball(&mut world, -45.0, 0.0);
+            spawn_ball(&mut world, -35.5, 0.0);
+            spawn_ball(&mut world, 45.5, 0.0);
+            spawn_ball(&mut world, 0.0, 30.0);
+            assert_eq!(cluster_sizes(&mut world), sizes, "{:?}", boundary);
+        }
+    }
+
+    #[test]
+    fn bonded_balls_cluster() {
+        let mut world = world(Boundary::Reflect);
+        let a = spawn_ball(&mut world, -30.0, 0.0);
+        let b = spawn_ball(&mut world, 30.0, 0.0);
+        spawn_ball(&mut world, 0.0, 30.0);
+        world.spawn(DistanceJoint { a, b, length: 60.0 });
+        assert_eq!(cluster_sizes(&mut world), vec![2, 1]);
+    }
+}
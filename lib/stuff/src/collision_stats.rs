@@ -0,0 +1,184 @@
+use crate::ball::{Ball, Contacts, PhysicsSet, Stats, Velocity, WorldBounds};
+use crate::compound::CompoundPart;
+use crate::sleep::Sleeping;
+use bevy::app::{App, AppExit, FixedUpdate, Last, Plugin};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::{
+    resource_exists, Entity, EventReader, IntoSystemConfigs, Local, Query, Res, ResMut, Resource,
+    Time, Transform, With, Without,
+};
+use bevy::utils::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Turns on the per-ball counters in `Stats`.
+#[derive(Resource, Default)]
+pub struct CollisionStatsConfig {
+    /// Where to write the per-ball counters as CSV at the end of the run
+    pub output: Option<PathBuf>,
+}
+
+/// Counts collisions and free paths for each ball, as diagnostics and at exit.
+#[derive(Default)]
+pub struct CollisionStatsPlugin;
+
+impl Plugin for CollisionStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(Self::COLLISION_FREQUENCY))
+            .register_diagnostic(Diagnostic::new(Self::MEAN_FREE_PATH))
+            .add_systems(
+                FixedUpdate,
+                collision_stats_system
+                    .in_set(PhysicsSet::PostCollide)
+                    .run_if(resource_exists::<CollisionStatsConfig>),
+            )
+            .add_systems(
+                Last,
+                report_collision_stats_system.run_if(resource_exists::<CollisionStatsConfig>),
+            );
+    }
+}
+
+impl CollisionStatsPlugin {
+    /// Mean number of collisions per ball per second
+    pub const COLLISION_FREQUENCY: DiagnosticPath =
+        DiagnosticPath::const_new("collision_frequency");
+    /// Mean distance travelled between successive collisions
+    pub const MEAN_FREE_PATH: DiagnosticPath = DiagnosticPath::const_new("mean_free_path");
+}
+
+type CountedFilter = (With<Ball>, Without<CompoundPart>, Without<Sleeping>);
+
+/// A contact counts as a collision on the step it starts, not on every step it lasts,
+/// so resting contacts and soft overlaps are only counted once.
+pub fn collision_stats_system(
+    mut diagnostics: Diagnostics,
+    balls: Query<(Entity, &Velocity), CountedFilter>,
+    existing: Query<(), With<Ball>>,
+    contacts: Res<Contacts>,
+    mut stats: ResMut<Stats>,
+    time: Res<Time>,
+    mut touching: Local<HashSet<(Entity, Entity)>>,
+) {
+    let dt = time.delta_seconds();
+    stats.elapsed += dt;
+
+    // Use speed rather than displacement, so that warping doesn't count as travel
+    for (entity, velocity) in &balls {
+        let step = velocity.length() * dt;
+        let ball = stats.per_ball.entry(entity).or_default();
+        ball.distance += step;
+        ball.free_path += step;
+    }
+
+    // Forget balls that have been merged or shattered
+    stats
+        .per_ball
+        .retain(|entity, _| existing.contains(*entity));
+
+    let previous = std::mem::take(&mut *touching);
+    for &(a, b) in &contacts.pairs {
+        let pair = (a.min(b), a.max(b));
+        touching.insert(pair);
+        if previous.contains(&pair) {
+            continue;
+        }
+        for entity in [a, b] {
+            let ball = stats.per_ball.entry(entity).or_default();
+            ball.collisions += 1;
+            // The path before the first collision started somewhere arbitrary
+            if ball.collisions > 1 {
+                ball.total_free_path += ball.free_path;
+                ball.num_free_paths += 1;
+            }
+            ball.free_path = 0.0;
+        }
+    }
+
+    let num_balls = stats.per_ball.len();
+    if num_balls == 0 || stats.elapsed <= 0.0 {
+        return;
+    }
+    let collisions: usize = stats.per_ball.values().map(|b| b.collisions).sum();
+    let frequency = collisions as f32 / (num_balls as f32 * stats.elapsed);
+    diagnostics.add_measurement(&CollisionStatsPlugin::COLLISION_FREQUENCY, || {
+        frequency as f64
+    });
+
+    let (total, count) = stats.per_ball.values().fold((0.0, 0), |(total, count), b| {
+        (total + b.total_free_path, count + b.num_free_paths)
+    });
+    if count > 0 {
+        diagnostics.add_measurement(&CollisionStatsPlugin::MEAN_FREE_PATH, || {
+            (total / count as f32) as f64
+        });
+    }
+}
+
+/// Print the collision statistics, alongside the hard-disk prediction, and write the
+/// per-ball counters when the app exits.
+pub fn report_collision_stats_system(
+    mut exit: EventReader<AppExit>,
+    config: Res<CollisionStatsConfig>,
+    stats: Res<Stats>,
+    balls: Query<&Transform, (With<Ball>, Without<CompoundPart>)>,
+    bounds: Res<WorldBounds>,
+) {
+    if exit.read().next().is_none() || stats.per_ball.is_empty() || stats.elapsed <= 0.0 {
+        return;
+    }
+
+    let num_balls = stats.per_ball.len() as f32;
+    let collisions: usize = stats.per_ball.values().map(|b| b.collisions).sum();
+    let distance: f32 = stats.per_ball.values().map(|b| b.distance).sum();
+    let (total, count) = stats.per_ball.values().fold((0.0, 0), |(total, count), b| {
+        (total + b.total_free_path, count + b.num_free_paths)
+    });
+
+    println!(
+        "Collision frequency per ball: {:.3} /s",
+        collisions as f32 / (num_balls * stats.elapsed)
+    );
+    println!(
+        "Mean free path: {:.2}, distance per collision: {:.2}",
+        if count > 0 {
+            total / count as f32
+        } else {
+            f32::NAN
+        },
+        distance / collisions.max(1) as f32
+    );
+
+    // Hard disks of diameter d at number density n have a collision cross-section of
+    // 2d, so lambda = 1 / (2 sqrt(2) n d)
+    let diameters: Vec<f32> = balls.iter().map(|t| t.scale.x).collect();
+    if !diameters.is_empty() {
+        let density = diameters.len() as f32 / bounds.area();
+        let diameter = diameters.iter().sum::<f32>() / diameters.len() as f32;
+        println!(
+            "Hard-disk mean free path: {:.2}",
+            1.0 / (2.0 * std::f32::consts::SQRT_2 * density * diameter)
+        );
+    }
+
+    let Some(path) = &config.output else {
+        return;
+    };
+    let mut csv = String::from("entity,collisions,distance,frequency,mean_free_path\n");
+    for (entity, ball) in &stats.per_ball {
+        csv += &format!(
+            "{},{},{:.3},{:.4},{}\n",
+            entity.index(),
+            ball.collisions,
+            ball.distance,
+            ball.collisions as f32 / stats.elapsed,
+            ball.mean_free_path()
+                .map_or(String::new(), |l| format!("{:.3}", l))
+        );
+    }
+    if let Err(error) =
+        std::fs::File::create(path).and_then(|mut file| file.write_all(csv.as_bytes()))
+    {
+        eprintln!("Failed to write {}: {}", path.display(), error);
+    }
+}
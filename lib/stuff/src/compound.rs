@@ -0,0 +1,404 @@
+use crate::ball::{
+    apply_velocity_system, ball_wall_system, ball_warp_system, Ball, Mass, PhysicsSet, Velocity,
+};
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::color::Color;
+use bevy::ecs::system::SystemParam;
+use bevy::math::{Quat, Vec2};
+use bevy::prelude::{
+    default, Assets, Circle, ColorMaterial, Commands, Component, Deref, DerefMut, Entity,
+    IntoSystemConfigs, Mesh, Query, Res, Time, Transform, With, Without,
+};
+use bevy::sprite::MaterialMesh2dBundle;
+
+/// A rigid body made of several balls welded together.
+///
+/// The body entity carries the shared `Transform` (centre of mass and orientation),
+/// `Velocity`, `AngularVelocity`, total `Mass` and `Inertia`. Its parts are ordinary
+/// balls, so the existing narrowphase can collide them with everything else.
+#[derive(Component)]
+pub struct CompoundBody {
+    pub parts: Vec<Entity>,
+}
+
+/// A ball that is one part of a compound body.
+///
+/// Each part carries the *whole* body's mass, so that whatever pushes a part, by
+/// changing its velocity by `impulse / mass` like any other ball, has given the body
+/// that impulse. `compound_response_system` then hands it on to the body.
+#[derive(Component)]
+pub struct CompoundPart {
+    pub body: Entity,
+    /// Position of the part's centre relative to the body, in the body's frame
+    pub offset: Vec2,
+}
+
+#[derive(Component, Deref, DerefMut)]
+pub struct AngularVelocity(pub f32);
+
+/// Moment of inertia about the centre of mass
+#[derive(Component)]
+pub struct Inertia(pub f32);
+
+/// True if both balls are parts of the same compound body, and so must not collide.
+pub fn welded(a: Option<&CompoundPart>, b: Option<&CompoundPart>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a.body == b.body)
+}
+
+type RigidFilter = (With<CompoundBody>, Without<Ball>);
+
+/// Compound bodies, as seen from the collision step.
+#[derive(SystemParam)]
+pub struct CompoundBodies<'w, 's> {
+    bodies: Query<'w, 's, (&'static Transform, &'static Mass, &'static Inertia), RigidFilter>,
+}
+
+impl CompoundBodies<'_, '_> {
+    /// How hard the body that `part` belongs to is to push along `normal` at `point`.
+    /// Once the body can turn that is less than its mass: 1/(1/M + (r x n)^2 / I), where
+    /// `r` is the offset of the point from the centre of mass.
+    pub fn effective_mass(&self, part: &CompoundPart, point: Vec2, normal: Vec2) -> Option<f32> {
+        let (transform, mass, inertia) = self.bodies.get(part.body).ok()?;
+        let rn = (point - transform.translation.truncate()).perp_dot(normal);
+        Some(1.0 / (1.0 / mass.0 + rn * rn / inertia.0))
+    }
+}
+
+struct Part {
+    offset: Vec2,
+    radius: f32,
+    mass: f32,
+}
+
+/// Builds a compound body out of circles.
+pub struct CompoundBodyBuilder {
+    parts: Vec<Part>,
+    color: Color,
+}
+
+impl Default for CompoundBodyBuilder {
+    fn default() -> Self {
+        Self {
+            parts: Vec::new(),
+            color: Color::WHITE,
+        }
+    }
+}
+
+impl CompoundBodyBuilder {
+    pub fn new() -> CompoundBodyBuilder {
+        Self::default()
+    }
+
+    /// Two equal circles joined by a gap
+    pub fn dumbbell(radius: f32, separation: f32, mass: f32) -> CompoundBodyBuilder {
+        let half = Vec2::new(separation / 2.0, 0.0);
+        Self::new()
+            .circle(-half, radius, mass / 2.0)
+            .circle(half, radius, mass / 2.0)
+    }
+
+    /// Two equal circles overlapping by half a radius
+    pub fn peanut(radius: f32, mass: f32) -> CompoundBodyBuilder {
+        Self::dumbbell(radius, 1.5 * radius, mass)
+    }
+
+    /// Add a circle, positioned relative to the other circles
+    pub fn circle(mut self, offset: Vec2, radius: f32, mass: f32) -> CompoundBodyBuilder {
+        self.parts.push(Part {
+            offset,
+            radius,
+            mass,
+        });
+        self
+    }
+
+    pub fn color(self, color: Color) -> CompoundBodyBuilder {
+        CompoundBodyBuilder { color, ..self }
+    }
+
+    /// Spawn the body and its parts with the centre of mass at `position`.
+    /// Returns the body entity.
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+        position: Vec2,
+        velocity: Vec2,
+        angular_velocity: f32,
+    ) -> Entity {
+        let mass: f32 = self.parts.iter().map(|p| p.mass).sum();
+        let centre_of_mass = self.parts.iter().map(|p| p.mass * p.offset).sum::<Vec2>() / mass;
+
+        // Uniform discs, moved to the centre of mass with the parallel axis theorem
+        let inertia: f32 = self
+            .parts
+            .iter()
+            .map(|p| {
+                p.mass * (0.5 * p.radius * p.radius + (p.offset - centre_of_mass).length_squared())
+            })
+            .sum();
+
+        // Use the bounding diameter as the scale, so that warping sees the whole body
+        let bounding_radius = self
+            .parts
+            .iter()
+            .map(|p| (p.offset - centre_of_mass).length() + p.radius)
+            .fold(0.0, f32::max);
+
+        let body = commands
+            .spawn((
+                Transform::from_translation(position.extend(0.0))
+                    .with_scale(Vec2::splat(2.0 * bounding_radius).extend(1.0)),
+                Velocity(velocity),
+                AngularVelocity(angular_velocity),
+                Mass(mass),
+                Inertia(inertia),
+            ))
+            .id();
+
+        let material = materials.add(self.color);
+
+        let parts: Vec<Entity> = self
+            .parts
+            .iter()
+            .map(|p| {
+                let offset = p.offset - centre_of_mass;
+                commands
+                    .spawn((
+                        MaterialMesh2dBundle {
+                            mesh: meshes.add(Circle::default()).into(),
+                            material: material.clone(),
+                            transform: Transform::from_translation((position + offset).extend(0.0))
+                                .with_scale(Vec2::splat(2.0 * p.radius).extend(1.0)),
+                            ..default()
+                        },
+                        Ball,
+                        Velocity(velocity + angular_velocity * offset.perp()),
+                        Mass(mass),
+                        CompoundPart { body, offset },
+                    ))
+                    .id()
+            })
+            .collect();
+
+        commands.entity(body).insert(CompoundBody { parts });
+        body
+    }
+}
+
+/// Moves compound bodies as single rigid bodies, passing the collisions of their
+/// parts on to the body.
+#[derive(Default)]
+pub struct CompoundPlugin;
+
+impl Plugin for CompoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                (compound_rotation_system, sync_compound_parts_system)
+                    .chain()
+                    .in_set(PhysicsSet::Integrate)
+                    .after(apply_velocity_system),
+                compound_response_system.in_set(PhysicsSet::PostCollide),
+                sync_compound_parts_system
+                    .in_set(PhysicsSet::Boundary)
+                    .after(ball_warp_system)
+                    .after(ball_wall_system),
+            ),
+        );
+    }
+}
+
+pub fn compound_rotation_system(
+    mut bodies: Query<(&mut Transform, &AngularVelocity), With<CompoundBody>>,
+    time: Res<Time>,
+) {
+    // The linear part is integrated along with everything else by apply_velocity_system
+    for (mut transform, angular_velocity) in &mut bodies {
+        transform.rotate_z(angular_velocity.0 * time.delta_seconds());
+    }
+}
+
+/// Place every part where its body says it should be, moving with the body.
+pub fn sync_compound_parts_system(
+    bodies: Query<(&Transform, &Velocity, &AngularVelocity, &CompoundBody), Without<Ball>>,
+    mut parts: Query<(&mut Transform, &mut Velocity, &CompoundPart), With<Ball>>,
+) {
+    for (body_transform, body_velocity, angular_velocity, body) in &bodies {
+        let rotation = body_transform.rotation;
+        let centre = body_transform.translation.truncate();
+
+        for &entity in &body.parts {
+            let Ok((mut transform, mut velocity, part)) = parts.get_mut(entity) else {
+                continue;
+            };
+            let r = rotate(rotation, part.offset);
+            transform.translation = (centre + r).extend(transform.translation.z);
+            velocity.0 = body_velocity.0 + angular_velocity.0 * r.perp();
+        }
+    }
+}
+
+type BodyQueryData = (
+    &'static mut Transform,
+    &'static mut Velocity,
+    &'static mut AngularVelocity,
+    &'static Mass,
+    &'static Inertia,
+    &'static CompoundBody,
+);
+
+/// Hand on to each body what the collision step, and anything else, did to its parts.
+///
+/// Parts carry the whole body's mass, so a part whose velocity has changed by `dv`
+/// has been given the impulse `M dv` at that point, and one that has moved by `dx`
+/// the positional impulse `M dx`. The body gets exactly those, so momentum given to a
+/// part is given to the body.
+pub fn compound_response_system(
+    mut bodies: Query<BodyQueryData, Without<Ball>>,
+    parts: Query<(&Transform, &Velocity, &Mass, &CompoundPart), With<Ball>>,
+) {
+    for (mut transform, mut velocity, mut angular_velocity, mass, inertia, body) in &mut bodies {
+        let centre = transform.translation.truncate();
+        let (mut impulse, mut angular_impulse) = (Vec2::ZERO, 0.0);
+        let (mut shift, mut angular_shift) = (Vec2::ZERO, 0.0);
+
+        for &entity in &body.parts {
+            let Ok((part_transform, part_velocity, part_mass, part)) = parts.get(entity) else {
+                continue;
+            };
+            // Where the part was put, and how it was moving, when it was last synced
+            let r = rotate(transform.rotation, part.offset);
+            let expected_velocity = velocity.0 + angular_velocity.0 * r.perp();
+            let expected_position = centre + r;
+
+            let j = part_mass.0 * (part_velocity.0 - expected_velocity);
+            impulse += j;
+            angular_impulse += r.perp_dot(j);
+
+            let p = part_mass.0 * (part_transform.translation.truncate() - expected_position);
+            shift += p;
+            angular_shift += r.perp_dot(p);
+        }
+
+        velocity.0 += impulse / mass.0;
+        angular_velocity.0 += angular_impulse / inertia.0;
+        transform.translation += (shift / mass.0).extend(0.0);
+        transform.rotate_z(angular_shift / inertia.0);
+    }
+}
+
+fn rotate(rotation: Quat, v: Vec2) -> Vec2 {
+    (rotation * v.extend(0.0)).truncate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accretion::MergeEvent;
+    use crate::ball::{naive_ball_collision_system, CollisionResponse, Contacts, Stats};
+    use crate::contact::{ActiveContactModel, ElasticModel};
+    use crate::fragmentation::ImpactEvent;
+    use bevy::ecs::event::Events;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+    use std::time::Duration;
+
+    /// Drop a ball onto one end of a resting dumbbell, off its centre of mass, and
+    /// return the world with the ball and the body.
+    fn off_centre_hit() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(10));
+        world.insert_resource(time);
+        world.insert_resource(ActiveContactModel::new(ElasticModel));
+        world.init_resource::<CollisionResponse>();
+        world.init_resource::<Stats>();
+        world.init_resource::<Contacts>();
+        world.init_resource::<Events<MergeEvent>>();
+        world.init_resource::<Events<ImpactEvent>>();
+
+        // Two parts of mass 1 and radius 5, 20 apart
+        let body = world
+            .spawn((
+                Transform::default(),
+                Velocity(Vec2::ZERO),
+                AngularVelocity(0.0),
+                Mass(2.0),
+                Inertia(2.0 * (12.5 + 100.0)),
+            ))
+            .id();
+        let parts = [-10.0, 10.0].map(|x| {
+            world
+                .spawn((
+                    Ball,
+                    Transform::from_xyz(x, 0.0, 0.0).with_scale(Vec2::splat(10.0).extend(1.0)),
+                    Velocity(Vec2::ZERO),
+                    Mass(2.0),
+                    CompoundPart {
+                        body,
+                        offset: Vec2::new(x, 0.0),
+                    },
+                ))
+                .id()
+        });
+        world.entity_mut(body).insert(CompoundBody {
+            parts: parts.to_vec(),
+        });
+
+        let ball = world
+            .spawn((
+                Ball,
+                Transform::from_xyz(10.0, 9.0, 0.0).with_scale(Vec2::splat(10.0).extend(1.0)),
+                Velocity(Vec2::new(0.0, -10.0)),
+                Mass(1.0),
+            ))
+            .id();
+
+        world.run_system_once(naive_ball_collision_system);
+        world.run_system_once(compound_response_system);
+        (world, ball, body)
+    }
+
+    #[test]
+    fn momentum_is_passed_on_to_the_body() {
+        let (world, ball, body) = off_centre_hit();
+        let ball_momentum = world.get::<Velocity>(ball).unwrap().0;
+        let body_momentum = 2.0 * world.get::<Velocity>(body).unwrap().0;
+        assert!((ball_momentum + body_momentum - Vec2::new(0.0, -10.0)).length() < 1e-4);
+
+        // Hit on the right, pushed down, so it turns clockwise
+        assert!(world.get::<AngularVelocity>(body).unwrap().0 < 0.0);
+    }
+
+    #[test]
+    fn elastic_hit_keeps_its_energy() {
+        let (world, ball, body) = off_centre_hit();
+        let ball_energy = 0.5 * world.get::<Velocity>(ball).unwrap().length_squared();
+        let body_energy = world.get::<Velocity>(body).unwrap().length_squared()
+            + 0.5 * 225.0 * world.get::<AngularVelocity>(body).unwrap().0.powi(2);
+        assert!((ball_energy + body_energy - 50.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn effective_mass_at_the_contact() {
+        let mut world = World::new();
+        let body = world
+            .spawn((Transform::default(), CompoundBody { parts: Vec::new() }))
+            .insert((Mass(2.0), Inertia(4.0)))
+            .id();
+        let masses = world.run_system_once(move |bodies: CompoundBodies| {
+            let part = CompoundPart {
+                body,
+                offset: Vec2::X,
+            };
+            [Vec2::X, Vec2::Y].map(|normal| bodies.effective_mass(&part, Vec2::X, normal))
+        });
+        // Pushed straight through the centre of mass, or turning it as well
+        assert_eq!(masses[0], Some(2.0));
+        assert!((masses[1].unwrap() - 4.0 / 3.0).abs() < 1e-6);
+    }
+}
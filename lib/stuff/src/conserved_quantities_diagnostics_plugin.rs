@@ -0,0 +1,109 @@
+use crate::ball::{Mass, Velocity};
+use crate::compound::{AngularVelocity, CompoundPart, Inertia};
+use bevy::app::{FixedFirst, FixedLast, Plugin};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::{Query, Res, ResMut, Resource, Transform, Without};
+use bevy::utils::HashMap;
+
+/// Potential energy of each kind of interaction, as of the last fixed step.
+///
+/// Force systems set or add to their own term while they compute forces, if this
+/// resource exists, so it only costs anything when the diagnostics are on. Every term
+/// is cleared at the start of each fixed step, so one whose system stops running
+/// drops out rather than going stale.
+#[derive(Resource, Default)]
+pub struct PotentialEnergy {
+    terms: HashMap<&'static str, f32>,
+}
+
+impl PotentialEnergy {
+    pub fn set(&mut self, term: &'static str, energy: f32) {
+        self.terms.insert(term, energy);
+    }
+
+    pub fn add(&mut self, term: &'static str, energy: f32) {
+        *self.terms.entry(term).or_default() += energy;
+    }
+
+    pub fn get(&self, term: &str) -> Option<f32> {
+        self.terms.get(term).copied()
+    }
+
+    pub fn total(&self) -> f32 {
+        self.terms.values().sum()
+    }
+}
+
+/// Adds FixedUpdate diagnostics for the quantities that physics should conserve:
+/// kinetic, potential and total energy, linear momentum, and angular momentum about
+/// the centre of the box.
+///
+/// # See also
+///
+/// [`LogDiagnosticsPlugin`](bevy_diagnostic::LogDiagnosticsPlugin) to output diagnostics to the console.
+#[derive(Default)]
+pub struct ConservedQuantitiesDiagnosticsPlugin;
+
+impl Plugin for ConservedQuantitiesDiagnosticsPlugin {
+    fn build(&self, app: &mut bevy_app::App) {
+        app.init_resource::<PotentialEnergy>()
+            .register_diagnostic(Diagnostic::new(Self::KINETIC_ENERGY))
+            .register_diagnostic(Diagnostic::new(Self::POTENTIAL_ENERGY))
+            .register_diagnostic(Diagnostic::new(Self::TOTAL_ENERGY))
+            .register_diagnostic(Diagnostic::new(Self::MOMENTUM_X))
+            .register_diagnostic(Diagnostic::new(Self::MOMENTUM_Y))
+            .register_diagnostic(Diagnostic::new(Self::ANGULAR_MOMENTUM))
+            .add_systems(FixedFirst, |mut potential: ResMut<PotentialEnergy>| {
+                potential.terms.clear()
+            })
+            .add_systems(FixedLast, Self::diagnostic_system);
+    }
+}
+
+type BodyQueryData = (
+    &'static Transform,
+    &'static Velocity,
+    &'static Mass,
+    Option<(&'static AngularVelocity, &'static Inertia)>,
+);
+
+impl ConservedQuantitiesDiagnosticsPlugin {
+    pub const KINETIC_ENERGY: DiagnosticPath = DiagnosticPath::const_new("kinetic_energy");
+    pub const POTENTIAL_ENERGY: DiagnosticPath = DiagnosticPath::const_new("potential_energy");
+    pub const TOTAL_ENERGY: DiagnosticPath = DiagnosticPath::const_new("total_energy");
+    pub const MOMENTUM_X: DiagnosticPath = DiagnosticPath::const_new("momentum_x");
+    pub const MOMENTUM_Y: DiagnosticPath = DiagnosticPath::const_new("momentum_y");
+    pub const ANGULAR_MOMENTUM: DiagnosticPath = DiagnosticPath::const_new("angular_momentum");
+
+    /// Parts of compound bodies carry the whole body's mass, so count the body instead.
+    pub fn diagnostic_system(
+        mut diagnostics: Diagnostics,
+        bodies: Query<BodyQueryData, Without<CompoundPart>>,
+        potential: Res<PotentialEnergy>,
+    ) {
+        let mut kinetic = 0.0;
+        let mut momentum = bevy::math::Vec2::ZERO;
+        let mut angular_momentum = 0.0;
+
+        for (transform, velocity, mass, rotation) in &bodies {
+            let x = transform.translation.truncate();
+            kinetic += 0.5 * mass.0 * velocity.length_squared();
+            momentum += mass.0 * velocity.0;
+            angular_momentum += mass.0 * x.perp_dot(velocity.0);
+
+            if let Some((omega, inertia)) = rotation {
+                kinetic += 0.5 * inertia.0 * omega.0 * omega.0;
+                angular_momentum += inertia.0 * omega.0;
+            }
+        }
+
+        let potential = potential.total();
+
+        diagnostics.add_measurement(&Self::KINETIC_ENERGY, || kinetic as f64);
+        diagnostics.add_measurement(&Self::POTENTIAL_ENERGY, || potential as f64);
+        diagnostics.add_measurement(&Self::TOTAL_ENERGY, || (kinetic + potential) as f64);
+        diagnostics.add_measurement(&Self::MOMENTUM_X, || momentum.x as f64);
+        diagnostics.add_measurement(&Self::MOMENTUM_Y, || momentum.y as f64);
+        diagnostics.add_measurement(&Self::ANGULAR_MOMENTUM, || angular_momentum as f64);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::ball::{separation, Ball, Boundary, Mass, PhysicsSet, Velocity, WorldBounds};
+use crate::conserved_quantities_diagnostics_plugin::PotentialEnergy;
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::ecs::query::Has;
+use bevy::ecs::system::SystemParam;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    Component, Entity, IntoSystemConfigs, Query, Res, ResMut, Resource, Time, Transform, With,
+};
+
+// Constraints live on their own entities, so that a ball can take part in any
+// number of them (chains, lattices, ...).
+
+/// Keeps two balls at a fixed distance from each other.
+#[derive(Component)]
+pub struct DistanceJoint {
+    pub a: Entity,
+    pub b: Entity,
+    pub length: f32,
+}
+
+/// Damped spring between two balls (Hooke's law with damping along the spring axis).
+#[derive(Component)]
+pub struct Spring {
+    pub a: Entity,
+    pub b: Entity,
+    pub rest_length: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+/// Keeps two balls no further apart than `max_length`, but lets them come closer.
+#[derive(Component)]
+pub struct Rope {
+    pub a: Entity,
+    pub b: Entity,
+    pub max_length: f32,
+}
+
+/// Keeps a ball at a fixed distance from a point in the world.
+///
+/// A `length` of zero nails the ball to the anchor; anything longer makes a pendulum.
+#[derive(Component)]
+pub struct Pin {
+    pub ball: Entity,
+    pub anchor: Vec2,
+    pub length: f32,
+}
+
+/// Every joint, spring and rope, for systems that need to know which balls are tied
+/// together.
+#[derive(SystemParam)]
+pub struct Bonds<'w, 's> {
+    joints: Query<'w, 's, &'static DistanceJoint>,
+    springs: Query<'w, 's, &'static Spring>,
+    ropes: Query<'w, 's, &'static Rope>,
+}
+
+impl Bonds<'_, '_> {
+    /// The two ends of each bond
+    pub fn pairs(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        let joints = self.joints.iter().map(|j| (j.a, j.b));
+        let springs = self.springs.iter().map(|s| (s.a, s.b));
+        let ropes = self.ropes.iter().map(|r| (r.a, r.b));
+        joints.chain(springs).chain(ropes)
+    }
+}
+
+#[derive(Resource)]
+pub struct ConstraintSolver {
+    /// Number of relaxation passes over the rigid constraints per fixed step
+    pub iterations: usize,
+}
+
+impl Default for ConstraintSolver {
+    fn default() -> Self {
+        Self { iterations: 4 }
+    }
+}
+
+/// Solves joints, springs, ropes and pins after the collision step.
+#[derive(Default)]
+pub struct ConstraintPlugin;
+
+impl Plugin for ConstraintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConstraintSolver>().add_systems(
+            FixedUpdate,
+            (spring_system, constraint_solver_system)
+                .chain()
+                .in_set(PhysicsSet::Constrain),
+        );
+    }
+}
+
+type BallQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Transform,
+        &'static mut Velocity,
+        &'static Mass,
+        Has<Sleeping>,
+    ),
+    With<Ball>,
+>;
+
+pub fn spring_system(
+    mut balls: BallQuery,
+    springs: Query<&Spring>,
+    boundary: Res<Boundary>,
+    bounds: Res<WorldBounds>,
+    time: Res<Time>,
+    potential: Option<ResMut<PotentialEnergy>>,
+) {
+    let dt = time.delta_seconds();
+    let box_size = boundary.periodic_box(&bounds);
+    let mut energy = 0.0;
+
+    for spring in &springs {
+        let Ok([(t1, mut v1, m1, s1), (t2, mut v2, m2, s2)]) =
+            balls.get_many_mut([spring.a, spring.b])
+        else {
+            continue;
+        };
+        if s1 && s2 {
+            continue;
+        }
+
+        let delta = separation(
+            t2.translation.truncate(),
+            t1.translation.truncate(),
+            box_size,
+        );
+        let distance = delta.length();
+        if distance == 0.0 {
+            continue;
+        }
+        let axis = delta / distance;
+
+        // Positive force pulls the balls together
+        let extension = distance - spring.rest_length;
+        let separation_speed = (v2.0 - v1.0).dot(axis);
+        let force = spring.stiffness * extension + spring.damping * separation_speed;
+
+        let impulse = force * dt * axis;
+        v1.0 += impulse / m1.0;
+        v2.0 -= impulse / m2.0;
+
+        energy += 0.5 * spring.stiffness * extension * extension;
+    }
+
+    if let Some(mut potential) = potential {
+        potential.set("springs", energy);
+    }
+}
+
+pub fn constraint_solver_system(
+    mut balls: BallQuery,
+    joints: Query<&DistanceJoint>,
+    ropes: Query<&Rope>,
+    pins: Query<&Pin>,
+    solver: Res<ConstraintSolver>,
+    boundary: Res<Boundary>,
+    bounds: Res<WorldBounds>,
+) {
+    let box_size = boundary.periodic_box(&bounds);
+
+    // Gauss-Seidel style relaxation: each constraint is solved exactly on its own,
+    // and the passes let neighbouring constraints settle against each other.
+    for _ in 0..solver.iterations {
+        for joint in &joints {
+            if let Ok([(mut t1, mut v1, m1, s1), (mut t2, mut v2, m2, s2)]) =
+                balls.get_many_mut([joint.a, joint.b])
+            {
+                solve_pair(
+                    (&mut t1, &mut v1, m1, s1),
+                    (&mut t2, &mut v2, m2, s2),
+                    joint.length,
+                    false,
+                    box_size,
+                );
+            }
+        }
+
+        for rope in &ropes {
+            if let Ok([(mut t1, mut v1, m1, s1), (mut t2, mut v2, m2, s2)]) =
+                balls.get_many_mut([rope.a, rope.b])
+            {
+                solve_pair(
+                    (&mut t1, &mut v1, m1, s1),
+                    (&mut t2, &mut v2, m2, s2),
+                    rope.max_length,
+                    true,
+                    box_size,
+                );
+            }
+        }
+
+        for pin in &pins {
+            if let Ok((mut transform, mut velocity, _, false)) = balls.get_mut(pin.ball) {
+                solve_pin(&mut transform, &mut velocity, pin, box_size);
+            }
+        }
+    }
+}
+
+/// One end of a constraint: the ball, and whether it is asleep.
+type End<'a> = (&'a mut Transform, &'a mut Velocity, &'a Mass, bool);
+
+fn solve_pair(
+    (t1, v1, m1, s1): End,
+    (t2, v2, m2, s2): End,
+    length: f32,
+    slack: bool,
+    box_size: Option<Vec2>,
+) {
+    if s1 && s2 {
+        return;
+    }
+
+    let delta = separation(
+        t2.translation.truncate(),
+        t1.translation.truncate(),
+        box_size,
+    );
+    let distance = delta.length();
+    if distance == 0.0 || (slack && distance <= length) {
+        return;
+    }
+    let axis = delta / distance;
+
+    // A sleeping end is held where it is, as if it were immovable
+    let w1 = if s1 { 0.0 } else { 1.0 / m1.0 };
+    let w2 = if s2 { 0.0 } else { 1.0 / m2.0 };
+    let inverse_mass_sum = w1 + w2;
+
+    // Position correction, shared out by inverse mass
+    let error = distance - length;
+    let correction = error / inverse_mass_sum * axis;
+    t1.translation += (w1 * correction).extend(0.0);
+    t2.translation -= (w2 * correction).extend(0.0);
+
+    // Remove the relative velocity along the axis (only the separating part for ropes)
+    let relative_velocity = (v2.0 - v1.0).dot(axis);
+    if slack && relative_velocity < 0.0 {
+        return;
+    }
+    let impulse = relative_velocity / inverse_mass_sum * axis;
+    v1.0 += w1 * impulse;
+    v2.0 -= w2 * impulse;
+}
+
+fn solve_pin(
+    transform: &mut Transform,
+    velocity: &mut Velocity,
+    pin: &Pin,
+    box_size: Option<Vec2>,
+) {
+    let delta = separation(transform.translation.truncate(), pin.anchor, box_size);
+    let distance = delta.length();
+
+    if distance == 0.0 || pin.length == 0.0 {
+        transform.translation = pin.anchor.extend(transform.translation.z);
+        velocity.0 = Vec2::ZERO;
+        return;
+    }
+    let axis = delta / distance;
+
+    // The anchor is immovable, so the ball takes the whole correction
+    transform.translation = (pin.anchor + pin.length * axis).extend(transform.translation.z);
+    velocity.0 -= velocity.dot(axis) * axis;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+    use std::time::Duration;
+
+    /// Two balls either side of the left and right edges of a 100 x 100 box, 10 apart
+    /// around the back and 90 apart directly
+    fn world(boundary: Boundary) -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.init_resource::<ConstraintSolver>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(10));
+        world.insert_resource(time);
+        world.insert_resource(boundary);
+        world.insert_resource(WorldBounds::new(100.0, 100.0));
+        let mut ball = |x: f32| {
+            world
+                .spawn((
+                    Ball,
+                    Transform::from_xyz(x, 0.0, 0.0),
+                    Velocity(Vec2::ZERO),
+                    Mass(1.0),
+                ))
+                .id()
+        };
+        let (a, b) = (ball(45.0), ball(-45.0));
+        (world, a, b)
+    }
+
+    fn x(world: &World, entity: Entity) -> f32 {
+        world.get::<Transform>(entity).unwrap().translation.x
+    }
+
+    #[test]
+    fn joint_holds_across_the_wrap() {
+        let (mut world, a, b) = world(Boundary::Wrap);
+        world.spawn(DistanceJoint { a, b, length: 10.0 });
+        world.run_system_once(constraint_solver_system);
+        assert_eq!(x(&world, a), 45.0);
+        assert_eq!(x(&world, b), -45.0);
+    }
+
+    #[test]
+    fn joint_pulls_together_between_walls() {
+        let (mut world, a, b) = world(Boundary::Reflect);
+        world.spawn(DistanceJoint { a, b, length: 10.0 });
+        world.run_system_once(constraint_solver_system);
+        assert!((x(&world, a) - 5.0).abs() < 1e-4);
+        assert!((x(&world, b) + 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn spring_rests_across_the_wrap() {
+        for (boundary, at_rest) in [(Boundary::Wrap, true), (Boundary::Reflect, false)] {
+            let (mut world, a, b) = world(boundary);
+            world.spawn(Spring {
+                a,
+                b,
+                rest_length: 10.0,
+                stiffness: 100.0,
+                damping: 1.0,
+            });
+            world.run_system_once(spring_system);
+            let velocity = world.get::<Velocity>(a).unwrap().0;
+            assert_eq!(velocity == Vec2::ZERO, at_rest, "{:?}", boundary);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use bevy::math::Vec2;
+use bevy::prelude::Resource;
+
+/// A pair of touching balls, as seen by a contact model.
+pub struct Contact {
+    /// Unit vector from the first ball towards the second
+    pub normal: Vec2,
+    /// How far the balls overlap
+    pub depth: f32,
+    /// Velocity of the second ball relative to the first
+    pub relative_velocity: Vec2,
+    pub m1: f32,
+    pub m2: f32,
+    pub r1: f32,
+    pub r2: f32,
+}
+
+/// What a contact model wants done about a contact.
+#[derive(Default)]
+pub struct ContactResponse {
+    /// Impulse applied to the second ball; the first receives the opposite
+    pub impulse: Vec2,
+    /// Position corrections for the first and second balls
+    pub correction1: Vec2,
+    pub correction2: Vec2,
+}
+
+/// Turns a contact into impulses and position corrections.
+///
+/// The collision systems hand every touching pair to the model in the
+/// `ActiveContactModel` resource, so a new model only needs to implement this.
+pub trait ContactModel: Send + Sync + 'static {
+    fn respond(&self, contact: &Contact, dt: f32) -> ContactResponse;
+
+    /// Energy stored in the contact, for models that let balls overlap
+    fn potential_energy(&self, _contact: &Contact) -> f32 {
+        0.0
+    }
+}
+
+/// The contact model used by the collision systems.
+#[derive(Resource)]
+pub struct ActiveContactModel(pub Box<dyn ContactModel>);
+
+impl ActiveContactModel {
+    pub fn new(model: impl ContactModel) -> ActiveContactModel {
+        ActiveContactModel(Box::new(model))
+    }
+}
+
+impl Default for ActiveContactModel {
+    fn default() -> Self {
+        Self::new(ImpulseModel::default())
+    }
+}
+
+/// Hard spheres: separate any overlap immediately and exchange an impulse along
+/// the normal, losing energy according to the coefficient of restitution.
+pub struct ImpulseModel {
+    pub restitution: f32,
+}
+
+impl Default for ImpulseModel {
+    fn default() -> Self {
+        Self { restitution: 0.5 }
+    }
+}
+
+impl ContactModel for ImpulseModel {
+    fn respond(&self, contact: &Contact, _dt: f32) -> ContactResponse {
+        // Use conservation of momentum to calculate new velocities
+        // https://en.wikipedia.org/wiki/Elastic_collision#Two-dimensional_collision_with_two_moving_objects
+
+        // Impulse model:
+        // https://en.wikipedia.org/wiki/Collision_response
+
+        // resolve overlap
+        let correction = (contact.depth / 2.0) * contact.normal;
+        let mut response = ContactResponse {
+            impulse: Vec2::ZERO,
+            correction1: -correction,
+            correction2: correction,
+        };
+
+        let relative_velocity = contact.relative_velocity.dot(contact.normal);
+        if relative_velocity > 0.0 {
+            // Already moving apart
+            return response;
+        }
+
+        let inverse_mass_sum = (1.0 / contact.m1) + (1.0 / contact.m2);
+
+        // Compute impulse
+        let impulse = -(1.0 + self.restitution) * relative_velocity / inverse_mass_sum;
+        response.impulse = impulse * contact.normal;
+        response
+    }
+}
+
+/// Hard spheres that lose no energy at all.
+#[derive(Default)]
+pub struct ElasticModel;
+
+impl ContactModel for ElasticModel {
+    fn respond(&self, contact: &Contact, dt: f32) -> ContactResponse {
+        ImpulseModel { restitution: 1.0 }.respond(contact, dt)
+    }
+}
+
+/// Soft spheres, as used in discrete element method (DEM) simulations.
+///
+/// Overlapping balls push each other apart with a Hertzian spring force
+/// `k * sqrt(R) * depth^(3/2)` (where `R` is the effective radius), plus a dashpot
+/// that damps the normal relative velocity. Overlap is never corrected directly,
+/// so the time step has to be short enough to resolve each contact.
+pub struct HertzianModel {
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+impl Default for HertzianModel {
+    fn default() -> Self {
+        Self {
+            stiffness: 500.0,
+            damping: 1.0,
+        }
+    }
+}
+
+impl ContactModel for HertzianModel {
+    fn respond(&self, contact: &Contact, dt: f32) -> ContactResponse {
+        let effective_radius = contact.r1 * contact.r2 / (contact.r1 + contact.r2);
+        let spring = self.stiffness * effective_radius.sqrt() * contact.depth.powf(1.5);
+        let dashpot = -self.damping * contact.relative_velocity.dot(contact.normal);
+
+        // Contacts push but never pull
+        let force = (spring + dashpot).max(0.0);
+
+        ContactResponse {
+            impulse: force * dt * contact.normal,
+            ..Default::default()
+        }
+    }
+
+    /// The integral of the spring force, `(2/5) k sqrt(R) depth^(5/2)`
+    fn potential_energy(&self, contact: &Contact) -> f32 {
+        let effective_radius = contact.r1 * contact.r2 / (contact.r1 + contact.r2);
+        0.4 * self.stiffness * effective_radius.sqrt() * contact.depth.max(0.0).powf(2.5)
+    }
+}
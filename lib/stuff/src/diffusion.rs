@@ -0,0 +1,322 @@
+use crate::ball::{Ball, PeriodicImage, PhysicsSet, Velocity};
+use crate::compound::CompoundPart;
+use bevy::app::{App, AppExit, FixedUpdate, Last, Plugin};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, Commands, Entity, EventReader, IntoSystemConfigs, Local, Query, Res, ResMut,
+    Resource, Time, Transform, With, Without,
+};
+use bevy::utils::HashMap;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Resource)]
+pub struct DiffusionConfig {
+    /// Sample the balls every this many fixed frames
+    pub every: u32,
+    /// Longest lag, in samples, of the MSD and VACF
+    pub max_lag: usize,
+    /// Where to write the MSD and VACF as CSV at the end of the run
+    pub output: Option<PathBuf>,
+}
+
+impl DiffusionConfig {
+    pub fn new(every: u32) -> DiffusionConfig {
+        DiffusionConfig {
+            every,
+            max_lag: 100,
+            output: None,
+        }
+    }
+}
+
+/// Unwrapped position and velocity of each ball at one sample.
+struct Sample {
+    balls: HashMap<Entity, (Vec2, Vec2)>,
+}
+
+/// Mean squared displacement and velocity autocorrelation, averaged over every
+/// sample as a time origin.
+#[derive(Resource, Default)]
+pub struct Diffusion {
+    /// Time between samples
+    pub interval: f32,
+    /// Sums over time origins, indexed by lag
+    msd: Vec<f32>,
+    vacf: Vec<f32>,
+    num_origins: Vec<usize>,
+    history: VecDeque<Sample>,
+}
+
+impl Diffusion {
+    pub fn num_lags(&self) -> usize {
+        self.num_origins.iter().take_while(|n| **n > 0).count()
+    }
+
+    /// Mean squared displacement after `lag` samples
+    pub fn msd(&self, lag: usize) -> f32 {
+        self.msd[lag] / self.num_origins[lag] as f32
+    }
+
+    /// Mean of `v(0) . v(t)` after `lag` samples
+    pub fn vacf(&self, lag: usize) -> f32 {
+        self.vacf[lag] / self.num_origins[lag] as f32
+    }
+
+    /// Einstein relation in 2D: MSD = 4Dt at long times. Fit the slope over the second
+    /// half of the lags, to leave out the ballistic start.
+    pub fn einstein_coefficient(&self) -> Option<f32> {
+        let num_lags = self.num_lags();
+        if num_lags < 4 {
+            return None;
+        }
+        let points: Vec<(f32, f32)> = (num_lags / 2..num_lags)
+            .map(|lag| (lag as f32 * self.interval, self.msd(lag)))
+            .collect();
+        let n = points.len() as f32;
+        let mean_t = points.iter().map(|(t, _)| t).sum::<f32>() / n;
+        let mean_msd = points.iter().map(|(_, msd)| msd).sum::<f32>() / n;
+        let (covariance, variance) = points.iter().fold((0.0, 0.0), |(c, v), (t, msd)| {
+            (
+                c + (t - mean_t) * (msd - mean_msd),
+                v + (t - mean_t) * (t - mean_t),
+            )
+        });
+        (variance > 0.0).then(|| covariance / variance / 4.0)
+    }
+
+    /// Green-Kubo relation in 2D: D = (1/2) integral of the VACF, by the trapezoid rule.
+    pub fn green_kubo_coefficient(&self) -> Option<f32> {
+        let num_lags = self.num_lags();
+        if num_lags < 2 {
+            return None;
+        }
+        let integral = (1..num_lags)
+            .map(|lag| 0.5 * (self.vacf(lag - 1) + self.vacf(lag)) * self.interval)
+            .sum::<f32>();
+        Some(integral / 2.0)
+    }
+}
+
+/// Follows balls across the periodic boundary to measure how they diffuse. The
+/// diffusion coefficients go to diagnostics as they settle, and the full curves are
+/// written out on exit.
+#[derive(Default)]
+pub struct DiffusionPlugin;
+
+impl Plugin for DiffusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Diffusion>()
+            .register_diagnostic(Diagnostic::new(Self::MEAN_SQUARED_DISPLACEMENT))
+            .register_diagnostic(Diagnostic::new(Self::EINSTEIN_COEFFICIENT))
+            .register_diagnostic(Diagnostic::new(Self::GREEN_KUBO_COEFFICIENT))
+            .add_systems(
+                FixedUpdate,
+                diffusion_system
+                    .after(PhysicsSet::Boundary)
+                    .run_if(resource_exists::<DiffusionConfig>),
+            )
+            .add_systems(
+                Last,
+                report_diffusion_system.run_if(resource_exists::<DiffusionConfig>),
+            );
+    }
+}
+
+impl DiffusionPlugin {
+    /// MSD at the longest lag measured so far
+    pub const MEAN_SQUARED_DISPLACEMENT: DiagnosticPath =
+        DiagnosticPath::const_new("diffusion/msd");
+    /// Diffusion coefficient from the slope of the MSD
+    pub const EINSTEIN_COEFFICIENT: DiagnosticPath =
+        DiagnosticPath::const_new("diffusion/einstein");
+    /// Diffusion coefficient from the integral of the VACF
+    pub const GREEN_KUBO_COEFFICIENT: DiagnosticPath =
+        DiagnosticPath::const_new("diffusion/green_kubo");
+}
+
+type DiffusionFilter = (With<Ball>, Without<CompoundPart>);
+
+pub fn diffusion_system(
+    mut commands: Commands,
+    mut diagnostics: Diagnostics,
+    balls: Query<(Entity, &Transform, &Velocity, Option<&PeriodicImage>), DiffusionFilter>,
+    config: Res<DiffusionConfig>,
+    mut diffusion: ResMut<Diffusion>,
+    time: Res<Time>,
+    mut frames: Local<u32>,
+) {
+    // Start counting images for new balls; they join the next sample
+    for (entity, _, _, image) in &balls {
+        if image.is_none() {
+            commands.entity(entity).insert(PeriodicImage::default());
+        }
+    }
+
+    *frames += 1;
+    if *frames < config.every {
+        return;
+    }
+    *frames = 0;
+
+    let sample = Sample {
+        balls: balls
+            .iter()
+            .filter_map(|(entity, transform, velocity, image)| {
+                image.map(|image| (entity, (image.unwrapped(transform), velocity.0)))
+            })
+            .collect(),
+    };
+    if sample.balls.is_empty() {
+        return;
+    }
+
+    let diffusion = &mut *diffusion;
+    diffusion.interval = time.delta_seconds() * config.every as f32;
+    diffusion.history.push_front(sample);
+    diffusion.history.truncate(config.max_lag + 1);
+    let num_lags = diffusion.history.len();
+    if diffusion.num_origins.len() < num_lags {
+        diffusion.msd.resize(num_lags, 0.0);
+        diffusion.vacf.resize(num_lags, 0.0);
+        diffusion.num_origins.resize(num_lags, 0);
+    }
+
+    // Each earlier sample is a time origin for the newest one. Balls that came or went
+    // in between are left out.
+    let now = &diffusion.history[0];
+    for (lag, origin) in diffusion.history.iter().enumerate() {
+        let (mut msd, mut vacf, mut count) = (0.0, 0.0, 0);
+        for (entity, (position, velocity)) in &now.balls {
+            if let Some((position0, velocity0)) = origin.balls.get(entity) {
+                msd += position.distance_squared(*position0);
+                vacf += velocity.dot(*velocity0);
+                count += 1;
+            }
+        }
+        if count > 0 {
+            diffusion.msd[lag] += msd / count as f32;
+            diffusion.vacf[lag] += vacf / count as f32;
+            diffusion.num_origins[lag] += 1;
+        }
+    }
+
+    let longest = diffusion.num_lags() - 1;
+    let msd = diffusion.msd(longest);
+    diagnostics.add_measurement(&DiffusionPlugin::MEAN_SQUARED_DISPLACEMENT, || msd as f64);
+    if let Some(d) = diffusion.einstein_coefficient() {
+        diagnostics.add_measurement(&DiffusionPlugin::EINSTEIN_COEFFICIENT, || d as f64);
+    }
+    if let Some(d) = diffusion.green_kubo_coefficient() {
+        diagnostics.add_measurement(&DiffusionPlugin::GREEN_KUBO_COEFFICIENT, || d as f64);
+    }
+}
+
+/// Print the diffusion coefficients and write the MSD and VACF when the app exits.
+pub fn report_diffusion_system(
+    mut exit: EventReader<AppExit>,
+    config: Res<DiffusionConfig>,
+    diffusion: Res<Diffusion>,
+) {
+    if exit.read().next().is_none() || diffusion.num_lags() == 0 {
+        return;
+    }
+
+    let format = |d: Option<f32>| d.map_or("n/a".to_string(), |d| format!("{:.3}", d));
+    println!(
+        "Diffusion coefficient: {} from MSD, {} from VACF",
+        format(diffusion.einstein_coefficient()),
+        format(diffusion.green_kubo_coefficient())
+    );
+
+    let Some(path) = &config.output else {
+        return;
+    };
+    let mut csv = String::from("time,msd,vacf,num_origins\n");
+    for lag in 0..diffusion.num_lags() {
+        csv += &format!(
+            "{},{:.4},{:.4},{}\n",
+            lag as f32 * diffusion.interval,
+            diffusion.msd(lag),
+            diffusion.vacf(lag),
+            diffusion.num_origins[lag]
+        );
+    }
+    if let Err(error) =
+        std::fs::File::create(path).and_then(|mut file| file.write_all(csv.as_bytes()))
+    {
+        eprintln!("Failed to write {}: {}", path.display(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::diagnostic::DiagnosticsStore;
+    use bevy::prelude::World;
+    use std::time::Duration;
+
+    const DT: f32 = 0.1;
+
+    #[test]
+    fn ballistic_motion_across_the_wrap() {
+        let mut world = World::new();
+        world.insert_resource(DiffusionConfig {
+            max_lag: 5,
+            ..DiffusionConfig::new(1)
+        });
+        world.init_resource::<Diffusion>();
+        world.init_resource::<DiagnosticsStore>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(DT));
+        world.insert_resource(time);
+        let system = world.register_system(diffusion_system);
+
+        let velocity = Vec2::new(3.0, 4.0);
+        let ball = world
+            .spawn((Ball, Transform::default(), Velocity(velocity)))
+            .id();
+        // The first run only starts counting the ball's images
+        world.run_system(system).unwrap();
+        for step in 0..10 {
+            let mut transform = world.get_mut::<Transform>(ball).unwrap();
+            transform.translation += (velocity * DT).extend(0.0);
+            if step == 4 {
+                // Sent around the box, as by `ball_warp_system`
+                transform.translation.x -= 100.0;
+                world.get_mut::<PeriodicImage>(ball).unwrap().offset.x -= 100.0;
+            }
+            world.run_system(system).unwrap();
+        }
+
+        let diffusion = world.resource::<Diffusion>();
+        assert_eq!(diffusion.num_lags(), 6);
+        for lag in 0..6 {
+            let distance = velocity.length() * lag as f32 * DT;
+            assert!((diffusion.msd(lag) - distance * distance).abs() < 1e-3);
+            assert!((diffusion.vacf(lag) - 25.0).abs() < 1e-3);
+        }
+        // Half the integral of a constant 25 over five intervals
+        let d = diffusion.green_kubo_coefficient().unwrap();
+        assert!((d - 0.5 * 25.0 * 5.0 * DT).abs() < 1e-3);
+    }
+
+    #[test]
+    fn einstein_slope_of_a_straight_msd() {
+        // MSD = 4 D t with D = 2, summed over three origins at every lag
+        let diffusion = Diffusion {
+            interval: 0.5,
+            msd: (0..10)
+                .map(|lag| 3.0 * 4.0 * 2.0 * lag as f32 * 0.5)
+                .collect(),
+            vacf: vec![0.0; 10],
+            num_origins: vec![3; 10],
+            history: VecDeque::new(),
+        };
+        assert_eq!(diffusion.num_lags(), 10);
+        assert!((diffusion.einstein_coefficient().unwrap() - 2.0).abs() < 1e-5);
+        assert_eq!(diffusion.green_kubo_coefficient(), Some(0.0));
+    }
+}
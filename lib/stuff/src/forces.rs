@@ -0,0 +1,141 @@
+use crate::ball::{apply_velocity_system, Mass, PhysicsSet, Velocity};
+use crate::compound::CompoundPart;
+use crate::conserved_quantities_diagnostics_plugin::PotentialEnergy;
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin, Update};
+use bevy::ecs::query::Has;
+use bevy::input::ButtonInput;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    Component, IntoSystemConfigs, KeyCode, Query, Res, ResMut, Resource, Time, Transform, Without,
+};
+
+/// Constant acceleration applied to every body, e.g. `Vec2::new(0.0, -100.0)`.
+#[derive(Resource)]
+pub struct Gravity {
+    pub acceleration: Vec2,
+    /// Toggled at runtime with the G key
+    pub enabled: bool,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self {
+            acceleration: Vec2::ZERO,
+            enabled: true,
+        }
+    }
+}
+
+/// Stokes drag, `F = -k * r * v`, for balls moving slowly through a viscous fluid.
+#[derive(Resource, Default)]
+pub struct LinearDrag(pub f32);
+
+/// Air drag, `F = -c * d * |v| * v`, proportional to the ball's cross-section
+/// (which is its diameter in 2D).
+#[derive(Resource, Default)]
+pub struct QuadraticDrag(pub f32);
+
+/// Bodies with this component don't fall.
+#[derive(Component)]
+pub struct IgnoreGravity;
+
+/// Bodies with this component don't feel Stokes drag.
+#[derive(Component)]
+pub struct IgnoreLinearDrag;
+
+/// Bodies with this component don't feel air drag.
+#[derive(Component)]
+pub struct IgnoreQuadraticDrag;
+
+/// Applies the global force fields to every moving body.
+#[derive(Default)]
+pub struct ForcesPlugin;
+
+impl Plugin for ForcesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Gravity>()
+            .init_resource::<LinearDrag>()
+            .init_resource::<QuadraticDrag>()
+            .add_systems(
+                FixedUpdate,
+                force_field_system
+                    .in_set(PhysicsSet::Integrate)
+                    .before(apply_velocity_system),
+            )
+            .add_systems(Update, toggle_gravity_system);
+    }
+}
+
+type ForceQueryData = (
+    &'static mut Velocity,
+    &'static Mass,
+    &'static Transform,
+    Has<IgnoreGravity>,
+    Has<IgnoreLinearDrag>,
+    Has<IgnoreQuadraticDrag>,
+    Has<Sleeping>,
+);
+
+/// Accelerate bodies under gravity and drag, ahead of `apply_velocity_system`.
+///
+/// Parts of compound bodies are skipped; the body itself feels the forces and the
+/// parts follow. Sleeping bodies don't move, but still count towards the potential
+/// energy.
+pub fn force_field_system(
+    mut query: Query<ForceQueryData, Without<CompoundPart>>,
+    gravity: Res<Gravity>,
+    linear_drag: Res<LinearDrag>,
+    quadratic_drag: Res<QuadraticDrag>,
+    time: Res<Time>,
+    potential: Option<ResMut<PotentialEnergy>>,
+) {
+    let dt = time.delta_seconds();
+    let g = if gravity.enabled {
+        gravity.acceleration
+    } else {
+        Vec2::ZERO
+    };
+
+    let mut energy = 0.0;
+
+    for (mut velocity, mass, transform, no_gravity, no_linear, no_quadratic, sleeping) in &mut query
+    {
+        let diameter = transform.scale.x;
+
+        if !no_gravity {
+            energy -= mass.0 * g.dot(transform.translation.truncate());
+        }
+        if sleeping {
+            continue;
+        }
+        if !no_gravity {
+            velocity.0 += g * dt;
+        }
+
+        // Drag per unit velocity. Applied implicitly, so that a large drag or time
+        // step slows a body down rather than reversing it.
+        let mut drag = 0.0;
+        if !no_linear {
+            drag += linear_drag.0 * (diameter / 2.0);
+        }
+        if !no_quadratic {
+            drag += quadratic_drag.0 * diameter * velocity.length();
+        }
+        velocity.0 /= 1.0 + drag * dt / mass.0;
+    }
+
+    if let Some(mut potential) = potential {
+        potential.set("gravity", energy);
+    }
+}
+
+pub fn toggle_gravity_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut gravity: ResMut<Gravity>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyG) {
+        gravity.enabled = !gravity.enabled;
+        println!("Gravity {}", if gravity.enabled { "on" } else { "off" });
+    }
+}
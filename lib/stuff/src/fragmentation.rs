@@ -0,0 +1,237 @@
+use crate::ball::{Ball, Mass, PhysicsSet, Velocity};
+use crate::compound::CompoundPart;
+use crate::random::random_float;
+use bevy::app::{App, AppExit, FixedUpdate, Last, Plugin};
+use bevy::math::Vec2;
+use bevy::prelude::{
+    default, resource_exists, ColorMaterial, Commands, Component, Entity, Event, EventReader,
+    Handle, IntoSystemConfigs, Query, Res, ResMut, Resource, Transform, With, Without,
+};
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use bevy::utils::HashSet;
+use bevy_prng::ChaCha8Rng;
+use bevy_rand::prelude::GlobalEntropy;
+
+#[derive(Resource)]
+pub struct FragmentationConfig {
+    /// Impact energy (head-on, centre of mass frame) above which balls shatter
+    pub energy_threshold: f32,
+    /// Number of pieces each shattered ball breaks into
+    pub fragments: usize,
+    /// Fraction of the energy dissipated by the collision that is lost rather than
+    /// driving the fragments apart
+    pub energy_loss: f32,
+    /// Balls whose pieces would be smaller than this don't shatter
+    pub min_diameter: f32,
+}
+
+impl FragmentationConfig {
+    pub fn new(energy_threshold: f32) -> FragmentationConfig {
+        FragmentationConfig {
+            energy_threshold,
+            fragments: 4,
+            energy_loss: 0.5,
+            min_diameter: 2.0,
+        }
+    }
+}
+
+/// A collision energetic enough to shatter the balls involved, sent by the
+/// collision systems when a `FragmentationConfig` is present.
+#[derive(Event)]
+pub struct ImpactEvent {
+    pub a: Entity,
+    pub b: Entity,
+    pub energy: f32,
+    /// Kinetic energy the contact model took out of the collision
+    pub dissipated: f32,
+}
+
+/// Marks a ball that was created by fragmentation.
+#[derive(Component)]
+pub struct Fragment;
+
+#[derive(Resource, Default)]
+pub struct FragmentationStats {
+    pub num_events: usize,
+    pub num_fragments: usize,
+}
+
+/// Shatters balls on high-energy impacts, and reports what became of them on exit.
+#[derive(Default)]
+pub struct FragmentationPlugin;
+
+impl Plugin for FragmentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ImpactEvent>()
+            .init_resource::<FragmentationStats>()
+            .add_systems(
+                FixedUpdate,
+                fragmentation_system
+                    .in_set(PhysicsSet::PostCollide)
+                    .run_if(resource_exists::<FragmentationConfig>),
+            )
+            .add_systems(
+                Last,
+                report_fragmentation_system.run_if(resource_exists::<FragmentationConfig>),
+            );
+    }
+}
+
+type ShatterQueryData = (
+    &'static Transform,
+    &'static Velocity,
+    &'static Mass,
+    &'static Mesh2dHandle,
+    &'static Handle<ColorMaterial>,
+);
+
+pub fn fragmentation_system(
+    mut commands: Commands,
+    mut events: EventReader<ImpactEvent>,
+    config: Res<FragmentationConfig>,
+    mut stats: ResMut<FragmentationStats>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    balls: Query<ShatterQueryData, (With<Ball>, Without<CompoundPart>)>,
+) {
+    let mut shattered = HashSet::new();
+
+    for event in events.read() {
+        // Some of the energy the collision dissipated drives the pieces apart.
+        // Share it between the balls.
+        let dispersal_energy = 0.5 * (1.0 - config.energy_loss) * event.dissipated;
+        let mut fragmented = false;
+
+        for entity in [event.a, event.b] {
+            if shattered.contains(&entity) {
+                continue;
+            }
+            let Ok((transform, velocity, mass, mesh, material)) = balls.get(entity) else {
+                continue;
+            };
+
+            // Random share of the mass (and area) for each piece
+            let weights: Vec<f32> = (0..config.fragments)
+                .map(|_| 0.2 + random_float(&mut rng))
+                .collect();
+            let total: f32 = weights.iter().sum();
+            let weights: Vec<f32> = weights.iter().map(|w| w / total).collect();
+
+            let diameter = transform.scale.x;
+            let smallest = weights.iter().copied().fold(f32::MAX, f32::min);
+            if diameter * smallest.sqrt() < config.min_diameter {
+                continue;
+            }
+
+            // Pieces fly out radially from where the ball was...
+            let base_angle = random_float(&mut rng) * 2.0 * std::f32::consts::PI;
+            let directions: Vec<Vec2> = (0..config.fragments)
+                .map(|i| {
+                    Vec2::from_angle(
+                        base_angle
+                            + 2.0 * std::f32::consts::PI * i as f32 / config.fragments as f32,
+                    )
+                })
+                .collect();
+
+            // ...with no net momentum relative to the parent...
+            let drift = weights
+                .iter()
+                .zip(&directions)
+                .map(|(w, d)| *w * *d)
+                .sum::<Vec2>();
+            let kicks: Vec<Vec2> = directions.iter().map(|d| *d - drift).collect();
+
+            // ...and scaled to carry the dispersal energy
+            let raw_energy: f32 = weights
+                .iter()
+                .zip(&kicks)
+                .map(|(w, k)| 0.5 * w * mass.0 * k.length_squared())
+                .sum();
+            let scale = if raw_energy > 0.0 {
+                (dispersal_energy / raw_energy).sqrt()
+            } else {
+                0.0
+            };
+
+            let radius = diameter / 2.0;
+            for ((weight, direction), kick) in weights.iter().zip(&directions).zip(&kicks) {
+                let fragment_diameter = diameter * weight.sqrt();
+                let offset = (radius - fragment_diameter / 2.0) * *direction;
+
+                commands.spawn((
+                    MaterialMesh2dBundle {
+                        mesh: mesh.clone(),
+                        material: material.clone(),
+                        transform: Transform::from_translation(
+                            transform.translation + offset.extend(0.0),
+                        )
+                        .with_scale(Vec2::splat(fragment_diameter).extend(1.0)),
+                        ..default()
+                    },
+                    Ball,
+                    Velocity(velocity.0 + scale * *kick),
+                    Mass(weight * mass.0),
+                    Fragment,
+                ));
+            }
+
+            commands.entity(entity).despawn();
+            shattered.insert(entity);
+            stats.num_fragments += config.fragments;
+            fragmented = true;
+        }
+
+        // Impacts where neither ball could break don't count
+        if fragmented {
+            stats.num_events += 1;
+        }
+    }
+}
+
+/// Print the fragment count and size distribution when the app exits.
+pub fn report_fragmentation_system(
+    mut exit: EventReader<AppExit>,
+    stats: Res<FragmentationStats>,
+    fragments: Query<&Transform, With<Fragment>>,
+) {
+    if exit.read().next().is_none() {
+        return;
+    }
+
+    println!(
+        "Fragmentation events: {}, fragments created: {}, fragments remaining: {}",
+        stats.num_events,
+        stats.num_fragments,
+        fragments.iter().len()
+    );
+
+    let diameters: Vec<f32> = fragments.iter().map(|t| t.scale.x).collect();
+    if diameters.is_empty() {
+        return;
+    }
+
+    let min = diameters.iter().copied().fold(f32::MAX, f32::min);
+    let max = diameters.iter().copied().fold(f32::MIN, f32::max);
+    let mean = diameters.iter().sum::<f32>() / diameters.len() as f32;
+    println!(
+        "Fragment diameter: min {:.2}, mean {:.2}, max {:.2}",
+        min, mean, max
+    );
+
+    const NUM_BINS: usize = 10;
+    let width = ((max - min) / NUM_BINS as f32).max(f32::EPSILON);
+    let mut bins = [0usize; NUM_BINS];
+    for diameter in &diameters {
+        let bin = (((diameter - min) / width) as usize).min(NUM_BINS - 1);
+        bins[bin] += 1;
+    }
+    for (i, count) in bins.iter().enumerate() {
+        println!(
+            "  {:7.2} - {:7.2}: {}",
+            min + i as f32 * width,
+            min + (i + 1) as f32 * width,
+            count
+        );
+    }
+}
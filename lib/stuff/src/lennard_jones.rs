@@ -0,0 +1,245 @@
+use crate::ball::{
+    separation, update_sorted_balls_cache, Ball, Boundary, Mass, PhysicsSet, SortedBallsCache,
+    Velocity, WorldBounds,
+};
+use crate::compound::{welded, CompoundPart};
+use crate::conserved_quantities_diagnostics_plugin::PotentialEnergy;
+use crate::pressure::Virial;
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::ecs::query::Has;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, Entity, IntoSystemConfigs, Query, Res, ResMut, Resource, Time, Transform, With,
+};
+use bevy::utils::HashMap;
+
+/// Soft Lennard-Jones pair potential, `V(r) = 4 epsilon ((sigma/r)^12 - (sigma/r)^6)`.
+///
+/// Each pair's `sigma` is chosen so that the potential minimum is where the two balls
+/// just touch, so ball sizes keep their meaning.
+#[derive(Resource)]
+pub struct LennardJonesConfig {
+    /// Depth of the potential well
+    pub epsilon: f32,
+    /// Pairs further apart than this many `sigma` don't interact
+    pub cutoff: f32,
+    /// Extra range kept in the neighbour list, so it only needs rebuilding once some
+    /// ball has moved more than half of this
+    pub skin: f32,
+}
+
+impl LennardJonesConfig {
+    pub fn new(epsilon: f32) -> LennardJonesConfig {
+        LennardJonesConfig {
+            epsilon,
+            cutoff: 2.5,
+            skin: 5.0,
+        }
+    }
+}
+
+/// Verlet neighbour list: every pair of balls within the cutoff plus the skin, as of
+/// the last rebuild.
+#[derive(Resource, Default)]
+pub struct NeighbourList {
+    pub pairs: Vec<(Entity, Entity)>,
+    /// Where each ball was at the last rebuild
+    reference: HashMap<Entity, Vec2>,
+    pub num_rebuilds: usize,
+}
+
+/// Lennard-Jones molecular dynamics, used in place of hard-sphere collisions.
+#[derive(Default)]
+pub struct LennardJonesPlugin;
+
+impl Plugin for LennardJonesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NeighbourList>().add_systems(
+            FixedUpdate,
+            (neighbour_list_system, lennard_jones_system)
+                .chain()
+                .in_set(PhysicsSet::Collide)
+                .after(update_sorted_balls_cache)
+                .run_if(resource_exists::<LennardJonesConfig>),
+        );
+    }
+}
+
+/// sigma = r_min / 2^(1/6)
+const SIGMA_PER_CONTACT: f32 = 0.890_898_7;
+
+/// Rebuild the neighbour list from the sweep and prune order when any ball has moved
+/// far enough that a pair outside the list could have come within the cutoff, or
+/// balls have come or gone.
+///
+/// When the box wraps, the sweep also supplies the pairs that are nearest around the
+/// back, and every pair is measured by its nearest periodic image.
+pub fn neighbour_list_system(
+    query: Query<(Entity, &Transform), With<Ball>>,
+    cache: Res<SortedBallsCache>,
+    config: Res<LennardJonesConfig>,
+    boundary: Res<Boundary>,
+    bounds: Res<WorldBounds>,
+    mut list: ResMut<NeighbourList>,
+) {
+    let half_skin_squared = (config.skin / 2.0).powi(2);
+    let stale = query.iter().len() != list.reference.len()
+        || query.iter().any(|(entity, transform)| {
+            list.reference.get(&entity).is_none_or(|x| {
+                x.distance_squared(transform.translation.truncate()) > half_skin_squared
+            })
+        });
+    if !stale {
+        return;
+    }
+
+    // Widen every ball enough that the largest pair's cutoff range is covered
+    let max_radius = query
+        .iter()
+        .map(|(_, t)| t.scale.x / 2.0)
+        .fold(0.0, f32::max);
+    let range_factor = config.cutoff * SIGMA_PER_CONTACT;
+    let margin = (range_factor - 1.0).max(0.0) * max_radius + config.skin / 2.0;
+
+    let box_size = boundary.periodic_box(&bounds);
+    let mut pairs = Vec::new();
+    cache.for_each_candidate_pair_periodic(margin, box_size.map(|size| size.x), |a, b| {
+        let (Ok((_, t1)), Ok((_, t2))) = (query.get(a), query.get(b)) else {
+            return;
+        };
+        let contact = (t1.scale.x + t2.scale.x) / 2.0;
+        let range = range_factor * contact + config.skin;
+        let r = separation(
+            t1.translation.truncate(),
+            t2.translation.truncate(),
+            box_size,
+        );
+        if r.length_squared() < range * range {
+            pairs.push((a, b));
+        }
+    });
+
+    list.pairs = pairs;
+    list.reference = query
+        .iter()
+        .map(|(entity, transform)| (entity, transform.translation.truncate()))
+        .collect();
+    list.num_rebuilds += 1;
+}
+
+type LennardJonesQueryData = (
+    &'static mut Velocity,
+    &'static Mass,
+    &'static Transform,
+    Has<Sleeping>,
+    Option<&'static CompoundPart>,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub fn lennard_jones_system(
+    mut query: Query<LennardJonesQueryData, With<Ball>>,
+    config: Res<LennardJonesConfig>,
+    list: Res<NeighbourList>,
+    boundary: Res<Boundary>,
+    bounds: Res<WorldBounds>,
+    time: Res<Time>,
+    potential: Option<ResMut<PotentialEnergy>>,
+    mut virial: Option<ResMut<Virial>>,
+) {
+    let dt = time.delta_seconds();
+    let box_size = boundary.periodic_box(&bounds);
+
+    // Shift the potential so that it goes to zero at the cutoff rather than jumping
+    let c6 = config.cutoff.powi(-6);
+    let shift = 4.0 * config.epsilon * (c6 * c6 - c6);
+    let mut energy = 0.0;
+
+    for &(a, b) in &list.pairs {
+        let Ok([(mut v1, m1, t1, s1, c1), (mut v2, m2, t2, s2, c2)]) = query.get_many_mut([a, b])
+        else {
+            continue;
+        };
+        if (s1 && s2) || welded(c1, c2) {
+            continue;
+        }
+
+        let sigma = SIGMA_PER_CONTACT * (t1.scale.x + t2.scale.x) / 2.0;
+        let r = separation(
+            t1.translation.truncate(),
+            t2.translation.truncate(),
+            box_size,
+        );
+        let distance = r.length();
+        if distance == 0.0 || distance > config.cutoff * sigma {
+            continue;
+        }
+
+        // F(r) = 24 epsilon / r * (2 (sigma/r)^12 - (sigma/r)^6), repulsive when positive
+        let s6 = (sigma / distance).powi(6);
+        let magnitude = 24.0 * config.epsilon / distance * (2.0 * s6 * s6 - s6);
+        let force = magnitude * r / distance;
+
+        v1.0 += force / m1.0 * dt;
+        v2.0 -= force / m2.0 * dt;
+
+        energy += 4.0 * config.epsilon * (s6 * s6 - s6) - shift;
+        if let Some(virial) = &mut virial {
+            virial.0 += r.dot(force) * dt;
+        }
+    }
+
+    if let Some(mut potential) = potential {
+        potential.set("lennard_jones", energy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+
+    /// Neighbour pairs of balls of diameter 10 at `xs` along the middle of a 200 x 200 box
+    fn neighbours(xs: &[f32], boundary: Boundary) -> Vec<(usize, usize)> {
+        let mut world = World::new();
+        world.insert_resource(LennardJonesConfig::new(1.0));
+        world.insert_resource(boundary);
+        world.insert_resource(WorldBounds {
+            size: Vec2::splat(200.0),
+        });
+        world.init_resource::<SortedBallsCache>();
+        world.init_resource::<NeighbourList>();
+        let entities: Vec<Entity> = xs
+            .iter()
+            .map(|&x| {
+                world
+                    .spawn((
+                        Ball,
+                        Transform::from_xyz(x, 0.0, 0.0).with_scale(Vec2::splat(10.0).extend(1.0)),
+                    ))
+                    .id()
+            })
+            .collect();
+        world.run_system_once(update_sorted_balls_cache);
+        world.run_system_once(neighbour_list_system);
+
+        let index = |e: Entity| entities.iter().position(|&x| x == e).unwrap();
+        let mut pairs: Vec<(usize, usize)> = world
+            .resource::<NeighbourList>()
+            .pairs
+            .iter()
+            .map(|&(a, b)| (index(a).min(index(b)), index(a).max(index(b))))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn neighbours_across_the_wrap() {
+        // Range is 2.5 sigma of contact plus the skin, about 27
+        let xs = [-95.0, -50.0, 0.0, 20.0, 95.0];
+        assert_eq!(neighbours(&xs, Boundary::Wrap), vec![(0, 4), (2, 3)]);
+        assert_eq!(neighbours(&xs, Boundary::Reflect), vec![(2, 3)]);
+    }
+}
@@ -1,8 +1,32 @@
+pub mod accretion;
 pub mod ball;
 pub mod benchmark;
+pub mod brownian;
+pub mod camera;
+pub mod charge;
 pub mod cli;
+pub mod clusters;
+pub mod collision_stats;
+pub mod compound;
+pub mod conserved_quantities_diagnostics_plugin;
+pub mod constraint;
+pub mod contact;
+pub mod diffusion;
 pub mod fixed_frame_count_diagnostics_plugin;
+pub mod forces;
+pub mod fragmentation;
+pub mod lennard_jones;
 pub mod my_color;
+pub mod nbody;
+pub mod packing;
+pub mod pressure;
 pub mod random;
 pub mod setup;
+pub mod sleep;
+pub mod soft_body;
+pub mod speed_distribution;
 pub mod stepping;
+pub mod structure;
+pub mod sweep;
+pub mod thermostat;
+pub mod union_find;
@@ -1,3 +1,4 @@
+use scarlet::colormap::{ColorMap, GradientColorMap};
 use scarlet::prelude::*;
 
 // For conversion from Scarlet to Bevy Color types - both are sRGB
@@ -18,3 +19,37 @@ impl From<MyColor> for bevy::color::Color {
         value.0
     }
 }
+
+/// Colour for an electric charge: red for positive, blue for negative, fading to
+/// white as the charge approaches zero. `max_charge` gets the strongest colour.
+pub fn charge_color(charge: f32, max_charge: f32) -> bevy::color::Color {
+    let white = RGBColor {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+    };
+    let end = if charge >= 0.0 {
+        RGBColor {
+            r: 1.0,
+            g: 0.1,
+            b: 0.1,
+        }
+    } else {
+        RGBColor {
+            r: 0.2,
+            g: 0.3,
+            b: 1.0,
+        }
+    };
+
+    let strength = if max_charge > 0.0 {
+        (charge.abs() / max_charge).min(1.0)
+    } else {
+        1.0
+    };
+
+    let color: MyColor = GradientColorMap::new_linear(white, end)
+        .transform_single(strength as f64)
+        .into();
+    color.into()
+}
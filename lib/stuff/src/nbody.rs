@@ -0,0 +1,340 @@
+use crate::ball::{apply_velocity_system, Mass, PhysicsSet, Velocity};
+use crate::compound::CompoundPart;
+use crate::conserved_quantities_diagnostics_plugin::PotentialEnergy;
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::ecs::query::Has;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, IntoSystemConfigs, Query, Res, ResMut, Resource, Time, Transform, Without,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NBodyMethod {
+    /// Approximate distant groups of bodies by their centre of mass, O(n log n)
+    BarnesHut,
+    /// Sum over every pair, O(n^2). Slow but exact, for validating Barnes-Hut.
+    Direct,
+}
+
+/// Mutual Newtonian gravity between all bodies with a `Mass`.
+#[derive(Resource)]
+pub struct NBodyConfig {
+    /// Gravitational constant
+    pub g: f32,
+    /// Plummer softening length, so close encounters don't blow up
+    pub softening: f32,
+    /// Barnes-Hut opening angle: a quadtree cell of width `s` at distance `d` is
+    /// treated as a single body when `s / d < theta`. Zero is equivalent to direct sum.
+    pub theta: f32,
+    pub method: NBodyMethod,
+}
+
+impl NBodyConfig {
+    pub fn new(g: f32) -> NBodyConfig {
+        NBodyConfig {
+            g,
+            softening: 5.0,
+            theta: 0.5,
+            method: NBodyMethod::BarnesHut,
+        }
+    }
+}
+
+/// Pulls every body towards every other body.
+#[derive(Default)]
+pub struct NBodyPlugin;
+
+impl Plugin for NBodyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            nbody_gravity_system
+                .in_set(PhysicsSet::Integrate)
+                .before(apply_velocity_system)
+                .run_if(resource_exists::<NBodyConfig>),
+        );
+    }
+}
+
+type NBodyQueryData = (
+    &'static mut Velocity,
+    &'static Mass,
+    &'static Transform,
+    Has<Sleeping>,
+);
+
+pub fn nbody_gravity_system(
+    mut query: Query<NBodyQueryData, Without<CompoundPart>>,
+    config: Res<NBodyConfig>,
+    time: Res<Time>,
+    potential: Option<ResMut<PotentialEnergy>>,
+) {
+    // Sleeping bodies still attract, they just don't move
+    let bodies: Vec<(Vec2, f32)> = query
+        .iter()
+        .map(|(_, mass, transform, _)| (transform.translation.truncate(), mass.0))
+        .collect();
+
+    // Acceleration and potential (per unit mass) at each body
+    let fields: Vec<(Vec2, f32)> = match config.method {
+        NBodyMethod::Direct => direct_sum(&bodies, config.softening),
+        NBodyMethod::BarnesHut => {
+            let tree = QuadTree::new(&bodies);
+            (0..bodies.len())
+                .map(|i| tree.field(&bodies, i, config.theta, config.softening))
+                .collect()
+        }
+    };
+
+    if let Some(mut potential) = potential {
+        // Each pair is counted from both ends
+        let energy: f32 = bodies
+            .iter()
+            .zip(&fields)
+            .map(|((_, m), (_, phi))| 0.5 * config.g * m * phi)
+            .sum();
+        potential.set("nbody", energy);
+    }
+
+    let dt = time.delta_seconds();
+    for ((mut velocity, _, _, sleeping), (acceleration, _)) in query.iter_mut().zip(fields) {
+        if !sleeping {
+            velocity.0 += config.g * acceleration * dt;
+        }
+    }
+}
+
+/// Acceleration and potential of a unit mass at `x` due to mass `m` at `y`, with G = 1.
+fn pull(x: Vec2, y: Vec2, m: f32, softening: f32) -> (Vec2, f32) {
+    let r = y - x;
+    let d2 = r.length_squared() + softening * softening;
+    let d = d2.sqrt();
+    (m * r / (d2 * d), -m / d)
+}
+
+fn direct_sum(bodies: &[(Vec2, f32)], softening: f32) -> Vec<(Vec2, f32)> {
+    let mut fields = vec![(Vec2::ZERO, 0.0); bodies.len()];
+    for i in 0..bodies.len() {
+        let (x1, m1) = bodies[i];
+        for j in (i + 1)..bodies.len() {
+            let (x2, m2) = bodies[j];
+            // Newton's third law: one evaluation per pair
+            let (a, phi) = pull(x1, x2, 1.0, softening);
+            fields[i].0 += m2 * a;
+            fields[i].1 += m2 * phi;
+            fields[j].0 -= m1 * a;
+            fields[j].1 += m1 * phi;
+        }
+    }
+    fields
+}
+
+/// Bodies closer together than this are kept in the same leaf rather than
+/// subdividing forever.
+const MAX_DEPTH: usize = 24;
+
+/// Directions from a cell's centre to the centres of its children, in the order
+/// given by `Node::quadrant`
+const QUADRANTS: [Vec2; 4] = [
+    Vec2::new(-1.0, -1.0),
+    Vec2::new(1.0, -1.0),
+    Vec2::new(-1.0, 1.0),
+    Vec2::new(1.0, 1.0),
+];
+
+struct Node {
+    centre: Vec2,
+    half_size: f32,
+    mass: f32,
+    /// Sum of mass * position, divided by `mass` to get the centre of mass
+    moment: Vec2,
+    /// Index of the first of four contiguous children, if subdivided
+    children: Option<usize>,
+    /// Bodies held by a leaf
+    bodies: Vec<usize>,
+}
+
+impl Node {
+    fn new(centre: Vec2, half_size: f32) -> Node {
+        Node {
+            centre,
+            half_size,
+            mass: 0.0,
+            moment: Vec2::ZERO,
+            children: None,
+            bodies: Vec::new(),
+        }
+    }
+
+    fn contains(&self, x: Vec2) -> bool {
+        (x - self.centre).abs().max_element() <= self.half_size
+    }
+
+    fn quadrant(&self, x: Vec2) -> usize {
+        (x.x >= self.centre.x) as usize + 2 * (x.y >= self.centre.y) as usize
+    }
+}
+
+/// Barnes-Hut quadtree
+/// https://en.wikipedia.org/wiki/Barnes%E2%80%93Hut_simulation
+struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+impl QuadTree {
+    fn new(bodies: &[(Vec2, f32)]) -> QuadTree {
+        let (min, max) = bodies.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), (x, _)| (min.min(*x), max.max(*x)),
+        );
+        let centre = (min + max) / 2.0;
+        let half_size = ((max - min).max_element() / 2.0).max(1.0);
+
+        let mut tree = QuadTree {
+            nodes: vec![Node::new(centre, half_size)],
+        };
+        for i in 0..bodies.len() {
+            tree.insert(bodies, i);
+        }
+        tree
+    }
+
+    fn insert(&mut self, bodies: &[(Vec2, f32)], i: usize) {
+        let (x, m) = bodies[i];
+        let mut node = 0;
+        let mut depth = 0;
+
+        loop {
+            self.nodes[node].mass += m;
+            self.nodes[node].moment += m * x;
+
+            if let Some(first) = self.nodes[node].children {
+                node = first + self.nodes[node].quadrant(x);
+                depth += 1;
+                continue;
+            }
+
+            if self.nodes[node].bodies.is_empty() || depth >= MAX_DEPTH {
+                self.nodes[node].bodies.push(i);
+                return;
+            }
+
+            // Occupied leaf: split it and push its body down a level
+            let first = self.nodes.len();
+            let half_size = self.nodes[node].half_size / 2.0;
+            let centre = self.nodes[node].centre;
+            for direction in QUADRANTS {
+                self.nodes
+                    .push(Node::new(centre + half_size * direction, half_size));
+            }
+            self.nodes[node].children = Some(first);
+
+            for j in std::mem::take(&mut self.nodes[node].bodies) {
+                let (y, n) = bodies[j];
+                let child = first + self.nodes[node].quadrant(y);
+                self.nodes[child].mass += n;
+                self.nodes[child].moment += n * y;
+                self.nodes[child].bodies.push(j);
+            }
+
+            // Undo the accumulation at this level; the loop adds it again
+            self.nodes[node].mass -= m;
+            self.nodes[node].moment -= m * x;
+        }
+    }
+
+    /// Acceleration and potential at body `i`
+    fn field(&self, bodies: &[(Vec2, f32)], i: usize, theta: f32, softening: f32) -> (Vec2, f32) {
+        let x = bodies[i].0;
+        let mut acceleration = Vec2::ZERO;
+        let mut potential = 0.0;
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.mass == 0.0 {
+                continue;
+            }
+
+            match node.children {
+                None => {
+                    for &j in &node.bodies {
+                        if j != i {
+                            let (a, phi) = pull(x, bodies[j].0, bodies[j].1, softening);
+                            acceleration += a;
+                            potential += phi;
+                        }
+                    }
+                }
+                Some(first) => {
+                    let centre_of_mass = node.moment / node.mass;
+                    let distance = x.distance(centre_of_mass);
+
+                    // Never approximate a cell the body is inside, or it would attract itself
+                    if !node.contains(x) && 2.0 * node.half_size < theta * distance {
+                        let (a, phi) = pull(x, centre_of_mass, node.mass, softening);
+                        acceleration += a;
+                        potential += phi;
+                    } else {
+                        stack.extend(first..first + 4);
+                    }
+                }
+            }
+        }
+
+        (acceleration, potential)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` bodies with masses between 1 and 2, scattered over a 100 x 100 square
+    fn scattered_bodies(n: usize) -> Vec<(Vec2, f32)> {
+        // A small linear congruential generator keeps the test repeatable
+        let mut state = 12345u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..n)
+            .map(|_| (100.0 * Vec2::new(next(), next()), 1.0 + next()))
+            .collect()
+    }
+
+    /// Largest Barnes-Hut acceleration error, relative to the rms direct-sum acceleration
+    fn worst_error(bodies: &[(Vec2, f32)], theta: f32) -> f32 {
+        let softening = 1.0;
+        let exact = direct_sum(bodies, softening);
+        let tree = QuadTree::new(bodies);
+        let rms = (exact.iter().map(|(a, _)| a.length_squared()).sum::<f32>()
+            / bodies.len() as f32)
+            .sqrt();
+        (0..bodies.len())
+            .map(|i| (tree.field(bodies, i, theta, softening).0 - exact[i].0).length() / rms)
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn barnes_hut_matches_direct_sum() {
+        let bodies = scattered_bodies(64);
+        // Ignoring the quadrupole moment of a cell costs of order theta^2
+        for theta in [0.3, 0.5, 0.8] {
+            let error = worst_error(&bodies, theta);
+            assert!(
+                error < 0.25 * theta * theta,
+                "theta {}: error {}",
+                theta,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn zero_theta_is_exact() {
+        let bodies = scattered_bodies(64);
+        assert!(worst_error(&bodies, 0.0) < 1e-4);
+    }
+}
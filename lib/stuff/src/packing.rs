@@ -0,0 +1,562 @@
+use crate::ball::{separation, Ball, Boundary, PhysicsSet, SortedBallsCache, WorldBounds};
+use bevy::app::{App, AppExit, FixedUpdate, Last, Plugin};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, Entity, EventReader, IntoSystemConfigs, Local, Query, Res, ResMut, Resource,
+    Transform, With,
+};
+use bevy::utils::HashMap;
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Resource)]
+pub struct PackingConfig {
+    /// Analyse the packing every this many fixed frames
+    pub every: u32,
+    /// Balls whose surfaces are closer than this count as in contact
+    pub tolerance: f32,
+    /// Where to write the per-ball measurements as CSV at the end of the run
+    pub output: Option<PathBuf>,
+}
+
+impl PackingConfig {
+    pub fn new(every: u32) -> PackingConfig {
+        PackingConfig {
+            every,
+            tolerance: 0.5,
+            output: None,
+        }
+    }
+}
+
+/// What the analysis found out about one ball.
+#[derive(Clone, Default)]
+pub struct PackedBall {
+    pub position: Vec2,
+    pub radius: f32,
+    /// Number of balls in contact
+    pub contacts: usize,
+    /// Area of the ball's Voronoi cell
+    pub cell_area: f32,
+    /// Number of Voronoi neighbours
+    pub neighbours: usize,
+    /// Local hexatic order, (1/n) sum over neighbours of exp(6i theta)
+    pub psi6: Vec2,
+}
+
+impl PackedBall {
+    /// Fraction of the ball's Voronoi cell that the ball covers
+    pub fn local_area_fraction(&self) -> f32 {
+        if self.cell_area > 0.0 {
+            PI * self.radius * self.radius / self.cell_area
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Result of the last analysis.
+#[derive(Resource, Default)]
+pub struct Packing {
+    pub balls: HashMap<Entity, PackedBall>,
+    /// Total ball area over box area
+    pub area_fraction: f32,
+}
+
+impl Packing {
+    fn mean(&self, f: impl Fn(&PackedBall) -> f32) -> f32 {
+        self.balls.values().map(f).sum::<f32>() / self.balls.len().max(1) as f32
+    }
+
+    pub fn mean_local_area_fraction(&self) -> f32 {
+        self.mean(PackedBall::local_area_fraction)
+    }
+
+    /// Mean number of contacts per ball
+    pub fn coordination_number(&self) -> f32 {
+        self.mean(|ball| ball.contacts as f32)
+    }
+
+    pub fn mean_neighbours(&self) -> f32 {
+        self.mean(|ball| ball.neighbours as f32)
+    }
+
+    /// Global hexatic order |<psi6>|: one for a perfect triangular lattice, near zero
+    /// for a liquid
+    pub fn hexatic_order(&self) -> f32 {
+        let sum: Vec2 = self.balls.values().map(|ball| ball.psi6).sum();
+        sum.length() / self.balls.len().max(1) as f32
+    }
+
+    /// Number of Voronoi cells with each number of sides
+    pub fn neighbour_distribution(&self) -> BTreeMap<usize, usize> {
+        let mut distribution = BTreeMap::new();
+        for ball in self.balls.values() {
+            *distribution.entry(ball.neighbours).or_default() += 1;
+        }
+        distribution
+    }
+}
+
+/// Characterises dense packings: area fraction, contact (coordination) number, and
+/// a Voronoi tessellation of the ball centres with the hexatic order it shows.
+///
+/// Contacts are found with the sorted order of `SortedBallsCache`, which
+/// `add_ball_physics_systems` keeps up to date whenever this is on. In a wrapping box
+/// they may be around the back.
+#[derive(Default)]
+pub struct PackingPlugin;
+
+impl Plugin for PackingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Packing>()
+            .register_diagnostic(Diagnostic::new(Self::AREA_FRACTION))
+            .register_diagnostic(Diagnostic::new(Self::LOCAL_AREA_FRACTION))
+            .register_diagnostic(Diagnostic::new(Self::COORDINATION_NUMBER))
+            .register_diagnostic(Diagnostic::new(Self::VORONOI_NEIGHBOURS))
+            .register_diagnostic(Diagnostic::new(Self::HEXATIC_ORDER))
+            .add_systems(
+                FixedUpdate,
+                packing_system
+                    .in_set(PhysicsSet::PostCollide)
+                    .run_if(resource_exists::<PackingConfig>),
+            )
+            .add_systems(
+                Last,
+                report_packing_system.run_if(resource_exists::<PackingConfig>),
+            );
+    }
+}
+
+impl PackingPlugin {
+    pub const AREA_FRACTION: DiagnosticPath = DiagnosticPath::const_new("packing/area_fraction");
+    /// Mean over balls of ball area over Voronoi cell area
+    pub const LOCAL_AREA_FRACTION: DiagnosticPath =
+        DiagnosticPath::const_new("packing/local_area_fraction");
+    pub const COORDINATION_NUMBER: DiagnosticPath =
+        DiagnosticPath::const_new("packing/coordination_number");
+    pub const VORONOI_NEIGHBOURS: DiagnosticPath =
+        DiagnosticPath::const_new("packing/voronoi_neighbours");
+    pub const HEXATIC_ORDER: DiagnosticPath = DiagnosticPath::const_new("packing/hexatic_order");
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn packing_system(
+    mut diagnostics: Diagnostics,
+    balls: Query<(Entity, &Transform), With<Ball>>,
+    cache: Res<SortedBallsCache>,
+    config: Res<PackingConfig>,
+    boundary: Res<Boundary>,
+    mut packing: ResMut<Packing>,
+    bounds: Res<WorldBounds>,
+    mut frames: Local<u32>,
+) {
+    *frames += 1;
+    if *frames < config.every {
+        return;
+    }
+    *frames = 0;
+
+    let box_size = bounds.size;
+
+    let mut packed: HashMap<Entity, PackedBall> = balls
+        .iter()
+        .map(|(entity, transform)| {
+            let ball = PackedBall {
+                position: transform.translation.truncate(),
+                radius: transform.scale.x / 2.0,
+                ..Default::default()
+            };
+            (entity, ball)
+        })
+        .collect();
+    if packed.is_empty() {
+        return;
+    }
+
+    let periodic_box = boundary.periodic_box(&bounds);
+    let margin = config.tolerance / 2.0;
+    cache.for_each_candidate_pair_periodic(margin, periodic_box.map(|size| size.x), |a, b| {
+        let (Some(ball_a), Some(ball_b)) = (packed.get(&a), packed.get(&b)) else {
+            return;
+        };
+        let distance = separation(ball_a.position, ball_b.position, periodic_box).length();
+        let gap = distance - ball_a.radius - ball_b.radius;
+        if gap < config.tolerance {
+            for entity in [a, b] {
+                packed.get_mut(&entity).unwrap().contacts += 1;
+            }
+        }
+    });
+
+    let entities: Vec<Entity> = packed.keys().copied().collect();
+    let sites: Vec<Vec2> = entities.iter().map(|e| packed[e].position).collect();
+    let cells = voronoi(&sites, box_size, periodic_box.is_some());
+    for (entity, cell) in entities.iter().zip(cells) {
+        let ball = packed.get_mut(entity).unwrap();
+        ball.cell_area = cell.area;
+        ball.neighbours = cell.neighbours.len();
+        ball.psi6 = psi6(&cell.neighbours);
+    }
+
+    let ball_area: f32 = packed.values().map(|b| PI * b.radius * b.radius).sum();
+    *packing = Packing {
+        balls: packed,
+        area_fraction: ball_area / (box_size.x * box_size.y),
+    };
+
+    for (path, value) in [
+        (PackingPlugin::AREA_FRACTION, packing.area_fraction),
+        (
+            PackingPlugin::LOCAL_AREA_FRACTION,
+            packing.mean_local_area_fraction(),
+        ),
+        (
+            PackingPlugin::COORDINATION_NUMBER,
+            packing.coordination_number(),
+        ),
+        (PackingPlugin::VORONOI_NEIGHBOURS, packing.mean_neighbours()),
+        (PackingPlugin::HEXATIC_ORDER, packing.hexatic_order()),
+    ] {
+        diagnostics.add_measurement(&path, || value as f64);
+    }
+}
+
+/// A site's Voronoi cell.
+pub struct VoronoiCell {
+    pub area: f32,
+    /// Separation from the site to each neighbour that shares an edge with the cell
+    pub neighbours: Vec<Vec2>,
+}
+
+/// Vertices of a convex polygon, each with the separation to the neighbour whose
+/// bisector the edge starting there lies on (`None` for the edge of the box).
+type Polygon = Vec<(Vec2, Option<Vec2>)>;
+
+/// Voronoi tessellation of `sites` in a box centred on the origin, either periodic
+/// or closed by the walls of the box.
+///
+/// Each cell starts as the box, centred on its site if periodic, and is cut by the
+/// bisectors with neighbours found on a grid, ring by ring, until no site further
+/// out could cut it any more.
+pub fn voronoi(sites: &[Vec2], box_size: Vec2, periodic: bool) -> Vec<VoronoiCell> {
+    // About one site per grid cell
+    let spacing = (box_size.x * box_size.y / sites.len() as f32).sqrt();
+    let columns = ((box_size.x / spacing) as i32).max(1);
+    let rows = ((box_size.y / spacing) as i32).max(1);
+    let cell_size = box_size / Vec2::new(columns as f32, rows as f32);
+    let half = box_size / 2.0;
+
+    let grid_cell = |x: Vec2| {
+        let x = if periodic {
+            (x + half).rem_euclid(box_size)
+        } else {
+            (x + half).clamp(Vec2::ZERO, box_size)
+        };
+        (
+            ((x.x / cell_size.x) as i32).clamp(0, columns - 1),
+            ((x.y / cell_size.y) as i32).clamp(0, rows - 1),
+        )
+    };
+    let mut grid: Vec<Vec<usize>> = vec![Vec::new(); (columns * rows) as usize];
+    for (i, site) in sites.iter().enumerate() {
+        let (column, row) = grid_cell(*site);
+        grid[(row * columns + column) as usize].push(i);
+    }
+
+    sites
+        .iter()
+        .enumerate()
+        .map(|(i, site)| {
+            let (low, high) = if periodic {
+                (-half, half)
+            } else {
+                (-half - *site, half - *site)
+            };
+            // Work relative to the site
+            let mut polygon: Polygon = vec![
+                (Vec2::new(low.x, low.y), None),
+                (Vec2::new(high.x, low.y), None),
+                (Vec2::new(high.x, high.y), None),
+                (Vec2::new(low.x, high.y), None),
+            ];
+
+            let (column, row) = grid_cell(*site);
+            let max_ring = columns.max(rows);
+            for ring in 0..=max_ring {
+                for dy in -ring..=ring {
+                    for dx in -ring..=ring {
+                        if dx.abs() != ring && dy.abs() != ring {
+                            continue;
+                        }
+                        let (c, r) = (column + dx, row + dy);
+                        let (c, r) = if periodic {
+                            (c.rem_euclid(columns), r.rem_euclid(rows))
+                        } else if (0..columns).contains(&c) && (0..rows).contains(&r) {
+                            (c, r)
+                        } else {
+                            continue;
+                        };
+                        for &j in &grid[(r * columns + c) as usize] {
+                            if j == i {
+                                continue;
+                            }
+                            let mut d = sites[j] - *site;
+                            if periodic {
+                                d -= box_size * (d / box_size).round();
+                            }
+                            if d != Vec2::ZERO {
+                                polygon = clip(&polygon, d);
+                            }
+                        }
+                    }
+                }
+
+                // Sites beyond twice the furthest vertex can't cut the cell
+                let reach = polygon.iter().map(|(v, _)| v.length()).fold(0.0, f32::max);
+                if 2.0 * reach <= ring as f32 * cell_size.min_element() {
+                    break;
+                }
+            }
+
+            let area = 0.5
+                * polygon
+                    .iter()
+                    .zip(polygon.iter().cycle().skip(1))
+                    .map(|((a, _), (b, _))| a.perp_dot(*b))
+                    .sum::<f32>();
+            let neighbours = polygon
+                .iter()
+                .zip(polygon.iter().cycle().skip(1))
+                .filter(|((a, _), (b, _))| a.distance(*b) > 1e-3)
+                .filter_map(|((_, neighbour), _)| *neighbour)
+                .collect();
+            VoronoiCell { area, neighbours }
+        })
+        .collect()
+}
+
+/// Local hexatic order from the directions to the neighbours, as a complex number
+fn psi6(neighbours: &[Vec2]) -> Vec2 {
+    if neighbours.is_empty() {
+        return Vec2::ZERO;
+    }
+    let sum: Vec2 = neighbours
+        .iter()
+        .map(|d| Vec2::from_angle(6.0 * d.to_angle()))
+        .sum();
+    sum / neighbours.len() as f32
+}
+
+/// Cut a polygon around the origin down to the side of the bisector with the site at
+/// `d` that the origin is on (Sutherland-Hodgman).
+fn clip(polygon: &Polygon, d: Vec2) -> Polygon {
+    let midpoint = d / 2.0;
+    let outside = |x: Vec2| (x - midpoint).dot(d) > 0.0;
+    let crossing = |a: Vec2, b: Vec2| {
+        let t = (midpoint - a).dot(d) / (b - a).dot(d);
+        a + t * (b - a)
+    };
+
+    let mut clipped = Polygon::with_capacity(polygon.len() + 1);
+    for (k, &(a, edge)) in polygon.iter().enumerate() {
+        let b = polygon[(k + 1) % polygon.len()].0;
+        match (outside(a), outside(b)) {
+            (false, false) => clipped.push((a, edge)),
+            (false, true) => {
+                clipped.push((a, edge));
+                clipped.push((crossing(a, b), Some(d)));
+            }
+            (true, false) => clipped.push((crossing(a, b), edge)),
+            (true, true) => (),
+        }
+    }
+    clipped
+}
+
+/// Print a summary of the packing and write the per-ball measurements when the app
+/// exits.
+pub fn report_packing_system(
+    mut exit: EventReader<AppExit>,
+    config: Res<PackingConfig>,
+    packing: Res<Packing>,
+) {
+    if exit.read().next().is_none() || packing.balls.is_empty() {
+        return;
+    }
+
+    println!(
+        "Area fraction {:.3} (local {:.3}), coordination number {:.2}",
+        packing.area_fraction,
+        packing.mean_local_area_fraction(),
+        packing.coordination_number()
+    );
+    println!(
+        "Voronoi neighbours {:.2}, hexatic order {:.3}",
+        packing.mean_neighbours(),
+        packing.hexatic_order()
+    );
+    for (neighbours, count) in packing.neighbour_distribution() {
+        println!("  {} sides: {}", neighbours, count);
+    }
+
+    let Some(path) = &config.output else {
+        return;
+    };
+    let mut csv = String::from("entity,x,y,radius,contacts,cell_area,neighbours,psi6\n");
+    for (entity, ball) in &packing.balls {
+        csv += &format!(
+            "{},{:.2},{:.2},{:.2},{},{:.2},{},{:.3}\n",
+            entity.index(),
+            ball.position.x,
+            ball.position.y,
+            ball.radius,
+            ball.contacts,
+            ball.cell_area,
+            ball.neighbours,
+            ball.psi6.length()
+        );
+    }
+    if let Err(error) =
+        std::fs::File::create(path).and_then(|mut file| file.write_all(csv.as_bytes()))
+    {
+        eprintln!("Failed to write {}: {}", path.display(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ball::update_sorted_balls_cache;
+    use bevy::diagnostic::DiagnosticsStore;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+
+    const SPACING: f32 = 10.0;
+
+    /// `columns` x `rows` sites on a square lattice filling a box
+    fn square_lattice(columns: usize, rows: usize) -> (Vec<Vec2>, Vec2) {
+        let box_size = SPACING * Vec2::new(columns as f32, rows as f32);
+        let sites = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                SPACING * Vec2::new(column as f32 + 0.5, row as f32 + 0.5) - box_size / 2.0
+            })
+            .collect();
+        (sites, box_size)
+    }
+
+    /// Triangular lattice that tiles a periodic box; `rows` must be even
+    fn triangular_lattice(columns: usize, rows: usize) -> (Vec<Vec2>, Vec2) {
+        let row_height = SPACING * 3f32.sqrt() / 2.0;
+        let box_size = Vec2::new(SPACING * columns as f32, row_height * rows as f32);
+        let sites = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let shift = if row % 2 == 0 { 0.25 } else { 0.75 };
+                Vec2::new(
+                    SPACING * (column as f32 + shift),
+                    row_height * (row as f32 + 0.5),
+                ) - box_size / 2.0
+            })
+            .collect();
+        (sites, box_size)
+    }
+
+    /// Scattered sites, from a fixed linear congruential sequence
+    fn scattered(count: usize, box_size: Vec2) -> Vec<Vec2> {
+        let mut state: u32 = 12345;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..count)
+            .map(|_| (Vec2::new(next(), next()) - 0.5) * box_size)
+            .collect()
+    }
+
+    fn total_area(cells: &[VoronoiCell]) -> f32 {
+        cells.iter().map(|cell| cell.area).sum()
+    }
+
+    #[test]
+    fn square_lattice_cells_are_squares() {
+        let (sites, box_size) = square_lattice(8, 6);
+        for cell in voronoi(&sites, box_size, true) {
+            assert_eq!(cell.neighbours.len(), 4);
+            assert!((cell.area - SPACING * SPACING).abs() < 1e-2);
+            assert!(psi6(&cell.neighbours).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn triangular_lattice_cells_are_hexagons() {
+        let (sites, box_size) = triangular_lattice(8, 6);
+        let cell_area = SPACING * SPACING * 3f32.sqrt() / 2.0;
+        for cell in voronoi(&sites, box_size, true) {
+            assert_eq!(cell.neighbours.len(), 6);
+            assert!((cell.area - cell_area).abs() < 1e-2);
+            assert!((psi6(&cell.neighbours).length() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn walls_leave_edge_cells_fewer_neighbours() {
+        let (sites, box_size) = square_lattice(4, 3);
+        let cells = voronoi(&sites, box_size, false);
+        let neighbours: Vec<usize> = cells.iter().map(|cell| cell.neighbours.len()).collect();
+        assert_eq!(neighbours, vec![2, 3, 3, 2, 3, 4, 4, 3, 2, 3, 3, 2]);
+        assert!((total_area(&cells) - box_size.x * box_size.y).abs() < 1e-1);
+    }
+
+    /// Packing of touching balls on a square lattice of 6 x 4 sites
+    fn packed_lattice(boundary: Boundary) -> Packing {
+        let (sites, box_size) = square_lattice(6, 4);
+        let mut world = World::new();
+        world.insert_resource(PackingConfig::new(1));
+        world.insert_resource(boundary);
+        world.insert_resource(WorldBounds { size: box_size });
+        world.init_resource::<Packing>();
+        world.init_resource::<SortedBallsCache>();
+        world.init_resource::<DiagnosticsStore>();
+        for site in sites {
+            world.spawn((
+                Ball,
+                Transform::from_translation(site.extend(0.0))
+                    .with_scale(Vec2::splat(SPACING).extend(1.0)),
+            ));
+        }
+        world.run_system_once(update_sorted_balls_cache);
+        world.run_system_once(packing_system);
+        world.remove_resource::<Packing>().unwrap()
+    }
+
+    #[test]
+    fn contacts_around_the_wrap() {
+        assert_eq!(packed_lattice(Boundary::Wrap).coordination_number(), 4.0);
+        // Against the walls: 4 corners with 2, 12 edges with 3, 8 inside with 4
+        let expected = (4.0 * 2.0 + 12.0 * 3.0 + 8.0 * 4.0) / 24.0;
+        let coordination = packed_lattice(Boundary::Reflect).coordination_number();
+        assert!((coordination - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cells_fill_the_box() {
+        let box_size = Vec2::new(400.0, 300.0);
+        let sites = scattered(200, box_size);
+        for periodic in [true, false] {
+            let cells = voronoi(&sites, box_size, periodic);
+            let area = total_area(&cells);
+            assert!(
+                (area - box_size.x * box_size.y).abs() < 1.0,
+                "periodic {}: area {}",
+                periodic,
+                area
+            );
+            assert!(cells.iter().all(|cell| cell.area > 0.0));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::ball::{Ball, Boundary, Mass, Velocity, WallImpulses, WorldBounds};
+use crate::compound::CompoundPart;
+use bevy::app::{App, FixedLast, Plugin};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::{
+    resource_exists, IntoSystemConfigs, Query, Res, ResMut, Resource, Time, With, Without,
+};
+use std::collections::VecDeque;
+
+/// Sum of `r_ij . J_ij` over the pair impulses of the current step, where `r_ij` is
+/// the separation of a pair and `J_ij` the impulse between them. Collision and
+/// soft-potential systems add to this while pressure is being measured.
+#[derive(Resource, Default)]
+pub struct Virial(pub f32);
+
+#[derive(Resource)]
+pub struct PressureConfig {
+    /// Number of fixed steps in the sliding averaging window
+    pub window: usize,
+}
+
+/// What one fixed step contributed to the pressure.
+struct PressureSample {
+    walls: [f32; 4],
+    virial: f32,
+    kinetic_energy: f32,
+    num_balls: usize,
+    dt: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct PressureHistory {
+    samples: VecDeque<PressureSample>,
+}
+
+/// Measures the pressure on each wall and the virial pressure, and compares PV with
+/// NkT, through diagnostics.
+///
+/// In 2D, pressure is force per unit length and the volume is the area of the box.
+/// Temperature is in units where Boltzmann's constant is one.
+#[derive(Default)]
+pub struct PressurePlugin;
+
+impl Plugin for PressurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PressureHistory>()
+            .register_diagnostic(Diagnostic::new(Self::PRESSURE_LEFT))
+            .register_diagnostic(Diagnostic::new(Self::PRESSURE_RIGHT))
+            .register_diagnostic(Diagnostic::new(Self::PRESSURE_BOTTOM))
+            .register_diagnostic(Diagnostic::new(Self::PRESSURE_TOP))
+            .register_diagnostic(Diagnostic::new(Self::WALL_PRESSURE))
+            .register_diagnostic(Diagnostic::new(Self::VIRIAL_PRESSURE))
+            .register_diagnostic(Diagnostic::new(Self::WALL_COMPRESSIBILITY))
+            .register_diagnostic(Diagnostic::new(Self::VIRIAL_COMPRESSIBILITY))
+            .add_systems(
+                FixedLast,
+                Self::diagnostic_system.run_if(resource_exists::<PressureConfig>),
+            );
+    }
+}
+
+type PressureFilter = (With<Ball>, Without<CompoundPart>);
+
+impl PressurePlugin {
+    pub const PRESSURE_LEFT: DiagnosticPath = DiagnosticPath::const_new("pressure/left");
+    pub const PRESSURE_RIGHT: DiagnosticPath = DiagnosticPath::const_new("pressure/right");
+    pub const PRESSURE_BOTTOM: DiagnosticPath = DiagnosticPath::const_new("pressure/bottom");
+    pub const PRESSURE_TOP: DiagnosticPath = DiagnosticPath::const_new("pressure/top");
+    pub const WALL_PRESSURE: DiagnosticPath = DiagnosticPath::const_new("pressure/walls");
+    pub const VIRIAL_PRESSURE: DiagnosticPath = DiagnosticPath::const_new("pressure/virial");
+    /// PV / NkT from the wall pressure; one for an ideal gas
+    pub const WALL_COMPRESSIBILITY: DiagnosticPath =
+        DiagnosticPath::const_new("pressure/walls_pv_over_nkt");
+    /// PV / NkT from the virial pressure
+    pub const VIRIAL_COMPRESSIBILITY: DiagnosticPath =
+        DiagnosticPath::const_new("pressure/virial_pv_over_nkt");
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn diagnostic_system(
+        mut diagnostics: Diagnostics,
+        balls: Query<(&Velocity, &Mass), PressureFilter>,
+        config: Res<PressureConfig>,
+        boundary: Res<Boundary>,
+        mut history: ResMut<PressureHistory>,
+        mut walls: ResMut<WallImpulses>,
+        mut virial: ResMut<Virial>,
+        bounds: Res<WorldBounds>,
+        time: Res<Time>,
+    ) {
+        let walls = std::mem::take(&mut *walls);
+        let virial = std::mem::take(&mut virial.0);
+
+        history.samples.push_back(PressureSample {
+            walls: [walls.left, walls.right, walls.bottom, walls.top],
+            virial,
+            kinetic_energy: balls
+                .iter()
+                .map(|(v, m)| 0.5 * m.0 * v.length_squared())
+                .sum(),
+            num_balls: balls.iter().len(),
+            dt: time.delta_seconds(),
+        });
+        while history.samples.len() > config.window {
+            history.samples.pop_front();
+        }
+
+        let duration: f32 = history.samples.iter().map(|s| s.dt).sum();
+        if duration <= 0.0 {
+            return;
+        }
+        let steps = history.samples.len() as f32;
+
+        let (width, height) = (bounds.width(), bounds.height());
+        let area = width * height;
+
+        // Force per unit length on each wall
+        let mut impulses = [0.0; 4];
+        for sample in &history.samples {
+            for (total, impulse) in impulses.iter_mut().zip(sample.walls) {
+                *total += impulse;
+            }
+        }
+        let pressures = [
+            impulses[0] / (duration * height),
+            impulses[1] / (duration * height),
+            impulses[2] / (duration * width),
+            impulses[3] / (duration * width),
+        ];
+        let wall_pressure = impulses.iter().sum::<f32>() / (duration * 2.0 * (width + height));
+
+        // Equipartition in 2D: kinetic energy is NkT. The virial theorem then gives
+        // PA = NkT + (1/2) <sum r_ij . F_ij>
+        let nkt = history
+            .samples
+            .iter()
+            .map(|s| s.kinetic_energy)
+            .sum::<f32>()
+            / steps;
+        let virial: f32 = history.samples.iter().map(|s| s.virial).sum::<f32>() / duration;
+        let virial_pressure = (nkt + 0.5 * virial) / area;
+
+        let num_balls = history.samples.iter().map(|s| s.num_balls).sum::<usize>() as f32 / steps;
+        if num_balls == 0.0 || nkt <= 0.0 {
+            return;
+        }
+
+        if *boundary == Boundary::Reflect {
+            for (path, pressure) in [
+                Self::PRESSURE_LEFT,
+                Self::PRESSURE_RIGHT,
+                Self::PRESSURE_BOTTOM,
+                Self::PRESSURE_TOP,
+            ]
+            .iter()
+            .zip(pressures)
+            {
+                diagnostics.add_measurement(path, || pressure as f64);
+            }
+            diagnostics.add_measurement(&Self::WALL_PRESSURE, || wall_pressure as f64);
+            diagnostics.add_measurement(&Self::WALL_COMPRESSIBILITY, || {
+                (wall_pressure * area / nkt) as f64
+            });
+        }
+        diagnostics.add_measurement(&Self::VIRIAL_PRESSURE, || virial_pressure as f64);
+        diagnostics.add_measurement(&Self::VIRIAL_COMPRESSIBILITY, || {
+            (virial_pressure * area / nkt) as f64
+        });
+    }
+}
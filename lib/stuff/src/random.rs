@@ -8,3 +8,11 @@ pub fn random_float(rng: &mut ResMut<GlobalEntropy<ChaCha8Rng>>) -> f32 {
     let random_u32 = rng.next_u32();
     random_u32 as f32 / u32::MAX as f32
 }
+
+/// Standard normal deviate, by the Box-Muller transform
+pub fn random_normal(rng: &mut ResMut<GlobalEntropy<ChaCha8Rng>>) -> f32 {
+    // Avoid ln(0)
+    let u1 = random_float(rng).max(f32::MIN_POSITIVE);
+    let u2 = random_float(rng);
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
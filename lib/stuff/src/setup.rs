@@ -1,42 +1,111 @@
-use crate::ball::{SortedBallsCache, Stats};
-use crate::benchmark::{run_benchmark, BenchmarkTargets};
-use crate::cli::{Cli, Command};
+use crate::accretion::AccretionPlugin;
+use crate::ball::{
+    apply_velocity_system, ball_wall_system, ball_warp_system, naive_ball_collision_system,
+    sweep_and_prune_collision_system, sweep_and_prune_collision_system_with_cache,
+    update_sorted_balls_cache, Boundary, CollisionResponse, Contacts, PhysicsSet, SortedBallsCache,
+    Stats, WallImpulses, WorldBounds,
+};
+use crate::benchmark::{
+    run_benchmark, BenchmarkReportConfig, BenchmarkTargets, PhaseTimingsPlugin,
+};
+use crate::brownian::BrownianPlugin;
+use crate::camera::WorldCameraPlugin;
+use crate::charge::{CoulombConfig, CoulombPlugin};
+use crate::cli::{Broadphase, Cli, Command, ContactModelKind};
+use crate::clusters::{ClusterConfig, ClusterPlugin};
+use crate::collision_stats::{CollisionStatsConfig, CollisionStatsPlugin};
+use crate::compound::CompoundPlugin;
+use crate::conserved_quantities_diagnostics_plugin::ConservedQuantitiesDiagnosticsPlugin;
+use crate::constraint::ConstraintPlugin;
+use crate::contact::{ActiveContactModel, ElasticModel, HertzianModel, ImpulseModel};
+use crate::diffusion::{DiffusionConfig, DiffusionPlugin};
 use crate::fixed_frame_count_diagnostics_plugin::FixedFrameCountDiagnosticsPlugin;
+use crate::forces::{ForcesPlugin, Gravity, LinearDrag, QuadraticDrag};
+use crate::fragmentation::{FragmentationConfig, FragmentationPlugin};
+use crate::lennard_jones::{LennardJonesConfig, LennardJonesPlugin};
+use crate::nbody::{NBodyConfig, NBodyMethod, NBodyPlugin};
+use crate::packing::{PackingConfig, PackingPlugin};
+use crate::pressure::{PressureConfig, PressurePlugin, Virial};
+use crate::sleep::SleepPlugin;
+use crate::soft_body::SoftBodyPlugin;
+use crate::speed_distribution::{SpeedDistributionPlugin, SpeedHistogramConfig};
 use crate::stepping;
-use bevy::app::{App, FixedUpdate, Update};
-use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
-use bevy::prelude::{default, Fixed, PluginGroup, Time, Val, Window, WindowPlugin};
+use crate::structure::{StructureConfig, StructurePlugin};
+use crate::sweep::{forwarded_args, run_sweep, SweepConfig};
+use crate::thermostat::{Thermostat, ThermostatPlugin};
+use bevy::app::{App, AppExit, FixedUpdate, ScheduleRunnerPlugin, Update};
+use bevy::asset::{AssetApp, AssetPlugin};
+use bevy::diagnostic::{DiagnosticsPlugin, FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::input::InputPlugin;
+use bevy::prelude::{
+    default, resource_equals, Fixed, IntoSystemConfigs, IntoSystemSetConfigs, Mesh, PluginGroup,
+    Res, Resource, Time, Val, Vec2, Window, WindowPlugin,
+};
+use bevy::sprite::ColorMaterial;
+use bevy::time::TimeUpdateStrategy;
 use bevy::window::PresentMode;
-use bevy::DefaultPlugins;
+use bevy::{DefaultPlugins, MinimalPlugins};
 use bevy_prng::ChaCha8Rng;
 use bevy_rand::plugin::EntropyPlugin;
+use std::time::Duration;
+
+/// Present when running without a window or renderer, for systems that only exist
+/// to change how things look.
+#[derive(Resource)]
+pub struct Headless;
 
 /// Common setup
+#[allow(clippy::default_constructed_unit_structs)]
 pub fn setup(cli: &Cli) -> App {
     // Construct seed
     let x = cli.global_opts.seed.to_le_bytes();
     let mut seed = [0u8; 32];
     seed[..x.len()].copy_from_slice(&x);
 
+    // A sweep only runs other benchmarks, so it never needs a window of its own
+    let headless = cli.global_opts.headless || matches!(cli.command, Some(Command::Sweep { .. }));
+
     let mut app = App::new();
-    app
-        // Disable VSYNC
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                // Turn off vsync to maximize CPU/GPU usage
-                //present_mode: PresentMode::AutoNoVsync,
-                present_mode: PresentMode::AutoVsync,
+    if headless {
+        // No window or renderer. Bins still spawn meshes and read the keyboard, so
+        // keep the assets and input, just nothing to show them with.
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)),
+            DiagnosticsPlugin,
+            InputPlugin,
+            AssetPlugin::default(),
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<ColorMaterial>()
+        .insert_resource(Headless)
+        // Advance time by exactly one physics step per update, so that FixedUpdate
+        // runs once each time round the loop
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / cli.global_opts.physics_rate,
+        )));
+    } else {
+        app
+            // Disable VSYNC
+            .add_plugins(DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(Window {
+                    // Turn off vsync to maximize CPU/GPU usage
+                    //present_mode: PresentMode::AutoNoVsync,
+                    present_mode: PresentMode::AutoVsync,
+                    ..default()
+                }),
                 ..default()
-            }),
-            ..default()
-        }))
-        // Enable stepping when compiled with '--features=bevy_debug_stepping'
-        .add_plugins(
-            stepping::SteppingPlugin::default()
-                .add_schedule(Update)
-                .add_schedule(FixedUpdate)
-                .at(Val::Percent(35.0), Val::Percent(50.0)),
-        )
+            }))
+            // Enable stepping when compiled with '--features=bevy_debug_stepping'
+            .add_plugins(
+                stepping::SteppingPlugin::default()
+                    .add_schedule(Update)
+                    .add_schedule(FixedUpdate)
+                    .at(Val::Percent(35.0), Val::Percent(50.0)),
+            )
+            // Scale the world to the window, rather than the other way round
+            .add_plugins(WorldCameraPlugin);
+    }
+    app
         // See the random number generator
         .add_plugins(EntropyPlugin::<ChaCha8Rng>::with_seed(seed))
         // Add diagnostics
@@ -47,26 +116,275 @@ pub fn setup(cli: &Cli) -> App {
 
     match cli.command {
         None => (),
+        Some(Command::Sweep {
+            ref balls,
+            ref broadphases,
+            ref rates,
+            frames,
+            ref output,
+        }) => {
+            let config = SweepConfig {
+                balls: balls.clone(),
+                broadphases: broadphases.clone(),
+                rates: rates.clone(),
+                frames,
+                seed: cli.global_opts.seed,
+                output: output.clone(),
+                args: forwarded_args(std::env::args().skip(1)),
+            };
+            // Run the benchmarks instead of the simulation
+            app.set_runner(move |_| {
+                run_sweep(&config);
+                AppExit::Success
+            });
+        }
         Some(Command::Benchmark {
             duration: None,
             frames: None,
+            ..
         }) => panic!("Benchmark command requires either a duration or a frame count"),
-        Some(Command::Benchmark { duration, frames }) => {
+        Some(Command::Benchmark {
+            duration,
+            frames,
+            ref report,
+        }) => {
             app.insert_resource(BenchmarkTargets {
                 duration,
                 fixed_frame_count: frames,
                 frame_count: None,
             });
+            app.insert_resource(BenchmarkReportConfig {
+                path: report.clone(),
+                seed: cli.global_opts.seed,
+            });
             app.add_systems(FixedUpdate, run_benchmark);
-            app.add_plugins(FixedFrameCountDiagnosticsPlugin::default());
+            app.add_plugins((
+                FixedFrameCountDiagnosticsPlugin::default(),
+                PhaseTimingsPlugin,
+            ));
         }
     }
 
     // Set the physics update rate (default is 64 Hz)
     app.insert_resource(Time::<Fixed>::from_hz(cli.global_opts.physics_rate));
 
+    app.configure_sets(
+        FixedUpdate,
+        (
+            PhysicsSet::Integrate,
+            PhysicsSet::Collide,
+            PhysicsSet::Constrain,
+            PhysicsSet::PostCollide,
+            PhysicsSet::Boundary,
+        )
+            .chain(),
+    );
+
+    app.add_plugins((
+        ConstraintPlugin,
+        SoftBodyPlugin,
+        CompoundPlugin,
+        AccretionPlugin,
+        FragmentationPlugin,
+        ForcesPlugin,
+        NBodyPlugin,
+        CoulombPlugin,
+        LennardJonesPlugin,
+        ThermostatPlugin,
+        BrownianPlugin,
+    ));
+
+    // Measurements, each of which does nothing until its config resource is inserted
+    app.add_plugins((
+        SpeedDistributionPlugin,
+        PressurePlugin,
+        CollisionStatsPlugin,
+        DiffusionPlugin,
+        StructurePlugin,
+        ClusterPlugin,
+        PackingPlugin,
+    ));
+
+    // Bins with a different default size insert their own
+    app.insert_resource(cli.world_bounds(WorldBounds::default()));
+
+    app.insert_resource(if cli.global_opts.walls {
+        Boundary::Reflect
+    } else {
+        Boundary::Wrap
+    });
+    app.insert_resource(WallImpulses::default());
+    app.add_systems(
+        FixedUpdate,
+        ball_wall_system
+            .in_set(PhysicsSet::Boundary)
+            .run_if(resource_equals(Boundary::Reflect)),
+    );
+
+    app.insert_resource(
+        match (cli.global_opts.lennard_jones, cli.global_opts.accrete) {
+            (Some(_), _) => CollisionResponse::None,
+            (None, Some(stickiness)) => CollisionResponse::Accrete { stickiness },
+            (None, None) => CollisionResponse::Bounce,
+        },
+    );
+
+    if let Some(epsilon) = cli.global_opts.lennard_jones {
+        app.insert_resource(LennardJonesConfig {
+            cutoff: cli.global_opts.lj_cutoff,
+            skin: cli.global_opts.skin,
+            ..LennardJonesConfig::new(epsilon)
+        });
+    }
+
+    app.insert_resource(match cli.global_opts.contact_model {
+        ContactModelKind::Impulse => ActiveContactModel::new(ImpulseModel::default()),
+        ContactModelKind::Elastic => ActiveContactModel::new(ElasticModel),
+        ContactModelKind::Hertzian => ActiveContactModel::new(HertzianModel::default()),
+    });
+
+    app.insert_resource(Gravity {
+        acceleration: Vec2::new(0.0, -cli.global_opts.gravity),
+        ..default()
+    });
+    app.insert_resource(LinearDrag(cli.global_opts.drag));
+    app.insert_resource(QuadraticDrag(cli.global_opts.air_drag));
+
+    if let Some(g) = cli.global_opts.nbody {
+        app.insert_resource(NBodyConfig {
+            softening: cli.global_opts.softening,
+            theta: cli.global_opts.opening_angle,
+            method: if cli.global_opts.direct_sum {
+                NBodyMethod::Direct
+            } else {
+                NBodyMethod::BarnesHut
+            },
+            ..NBodyConfig::new(g)
+        });
+    }
+
+    if let Some(k) = cli.global_opts.coulomb {
+        app.insert_resource(CoulombConfig {
+            cutoff: cli.global_opts.cutoff,
+            boundary: cli.global_opts.coulomb_boundary,
+            k_max: cli.global_opts.ewald_k_max,
+            auto_charge: cli.global_opts.charge,
+            ..CoulombConfig::new(k)
+        });
+    }
+
+    if let Some(kind) = cli.global_opts.thermostat {
+        app.insert_resource(Thermostat {
+            time_constant: cli.global_opts.thermostat_time,
+            ..Thermostat::new(kind, cli.global_opts.temperature)
+        });
+    }
+
+    if let Some(energy_threshold) = cli.global_opts.fragment {
+        app.insert_resource(FragmentationConfig::new(energy_threshold));
+    }
+
+    if let Some(every) = cli.global_opts.speed_histogram {
+        app.insert_resource(SpeedHistogramConfig {
+            output: cli.global_opts.speed_histogram_output.clone(),
+            ..SpeedHistogramConfig::new(every)
+        });
+    }
+
+    if let Some(window) = cli.global_opts.pressure {
+        app.insert_resource(PressureConfig { window });
+        app.insert_resource(Virial::default());
+    }
+
+    if cli.global_opts.collision_stats {
+        app.insert_resource(CollisionStatsConfig {
+            output: cli.global_opts.collision_stats_output.clone(),
+        });
+    }
+
+    if let Some(every) = cli.global_opts.diffusion {
+        app.insert_resource(DiffusionConfig {
+            max_lag: cli.global_opts.max_lag,
+            output: cli.global_opts.diffusion_output.clone(),
+            ..DiffusionConfig::new(every)
+        });
+    }
+
+    if let Some(every) = cli.global_opts.rdf {
+        app.insert_resource(StructureConfig {
+            window: cli.global_opts.rdf_window,
+            range: cli.global_opts.rdf_range,
+            max_mode: cli.global_opts.structure_factor,
+            rdf_output: cli.global_opts.rdf_output.clone(),
+            structure_factor_output: cli.global_opts.structure_factor_output.clone(),
+            ..StructureConfig::new(every)
+        });
+    }
+
+    if let Some(every) = cli.global_opts.clusters {
+        app.insert_resource(ClusterConfig {
+            tolerance: cli.global_opts.cluster_tolerance,
+            recolor: cli.global_opts.color_clusters,
+            output: cli.global_opts.clusters_output.clone(),
+            ..ClusterConfig::new(every)
+        });
+    }
+
+    if let Some(every) = cli.global_opts.packing {
+        app.insert_resource(PackingConfig {
+            tolerance: cli.global_opts.contact_tolerance,
+            output: cli.global_opts.packing_output.clone(),
+            ..PackingConfig::new(every)
+        });
+    }
+
+    if cli.global_opts.conservation {
+        app.add_plugins(ConservedQuantitiesDiagnosticsPlugin);
+    }
+
+    if cli.global_opts.sleep {
+        app.add_plugins(SleepPlugin);
+    }
+
     app.insert_resource(Stats::default());
+    app.insert_resource(Contacts::default());
 
     app.insert_resource(SortedBallsCache::default());
     app
 }
+
+/// Whether anything besides the cached sweep reads `SortedBallsCache`, which then has
+/// to be kept up to date whichever broadphase is in use.
+fn sorted_balls_cache_wanted(
+    lennard_jones: Option<Res<LennardJonesConfig>>,
+    structure: Option<Res<StructureConfig>>,
+    clusters: Option<Res<ClusterConfig>>,
+    packing: Option<Res<PackingConfig>>,
+) -> bool {
+    lennard_jones.is_some() || structure.is_some() || clusters.is_some() || packing.is_some()
+}
+
+/// Integrate, collide with the broadphase chosen on the command line, and wrap balls
+/// around the edges of the world.
+pub fn add_ball_physics_systems(app: &mut App, cli: &Cli) {
+    let update_cache = update_sorted_balls_cache.run_if(sorted_balls_cache_wanted);
+    let collide = match cli.global_opts.broadphase {
+        Broadphase::Naive => (update_cache, naive_ball_collision_system).into_configs(),
+        Broadphase::Sap => (update_cache, sweep_and_prune_collision_system).into_configs(),
+        Broadphase::SapCached => (
+            update_sorted_balls_cache,
+            sweep_and_prune_collision_system_with_cache,
+        )
+            .chain()
+            .into_configs(),
+    };
+    app.add_systems(
+        FixedUpdate,
+        (
+            apply_velocity_system.in_set(PhysicsSet::Integrate),
+            collide.in_set(PhysicsSet::Collide),
+            ball_warp_system.in_set(PhysicsSet::Boundary),
+        )
+            .chain(),
+    );
+}
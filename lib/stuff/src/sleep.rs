@@ -0,0 +1,309 @@
+use crate::ball::{Ball, Contacts, Mass, PhysicsSet, Velocity};
+use crate::compound::CompoundPart;
+use crate::constraint::Bonds;
+use crate::union_find::UnionFind;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::Vec2;
+use bevy::prelude::{
+    Commands, Component, Entity, IntoSystemConfigs, Query, Res, ResMut, Resource, With,
+};
+use bevy::utils::{HashMap, HashSet};
+
+/// A ball that has been put to sleep along with the rest of its island: the balls it
+/// is touching, tied to by joints, springs or ropes, or welded to in a compound body.
+///
+/// Sleeping balls are not integrated, and pairs of sleeping balls are skipped by
+/// the collision systems. The island is identified by one of its member entities.
+#[derive(Component)]
+pub struct Sleeping {
+    pub island: Entity,
+}
+
+#[derive(Resource)]
+pub struct SleepConfig {
+    /// Mean kinetic energy per ball below which an island is considered at rest
+    pub energy_threshold: f32,
+    /// Number of consecutive fixed steps an island must stay at rest before sleeping
+    pub steps: u32,
+    /// Speed above which a sleeping ball (and its island) is woken up
+    pub wake_speed: f32,
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        Self {
+            energy_threshold: 1.0,
+            steps: 64,
+            wake_speed: 2.0,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Islands {
+    pub num_islands: usize,
+    pub num_sleeping: usize,
+    low_energy_steps: HashMap<Entity, u32>,
+}
+
+/// Puts islands to sleep when they come to rest, and wakes them on contact.
+#[derive(Default)]
+pub struct SleepPlugin;
+
+impl Plugin for SleepPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SleepConfig>()
+            .init_resource::<Islands>()
+            .add_systems(
+                FixedUpdate,
+                (wake_system, island_sleep_system)
+                    .chain()
+                    .in_set(PhysicsSet::PostCollide),
+            );
+    }
+}
+
+/// Wake every island that contains a sleeping ball that has been given some velocity,
+/// either by a collision impulse or by anything else that touches `Velocity`. Balls
+/// that stay asleep lose whatever smaller velocity they were given, so that they
+/// don't carry it off when they do wake, or show up in the energy diagnostics.
+pub fn wake_system(
+    mut commands: Commands,
+    config: Res<SleepConfig>,
+    mut query: Query<(Entity, &Sleeping, &mut Velocity), With<Ball>>,
+) {
+    let wake_speed_squared = config.wake_speed * config.wake_speed;
+
+    let woken: HashSet<Entity> = query
+        .iter()
+        .filter(|(_, _, velocity)| velocity.length_squared() > wake_speed_squared)
+        .map(|(_, sleeping, _)| sleeping.island)
+        .collect();
+
+    for (entity, sleeping, mut velocity) in &mut query {
+        if woken.contains(&sleeping.island) {
+            commands.entity(entity).remove::<Sleeping>();
+        } else if velocity.0 != Vec2::ZERO {
+            velocity.0 = Vec2::ZERO;
+        }
+    }
+}
+
+type IslandQueryData = (
+    Entity,
+    &'static mut Velocity,
+    &'static Mass,
+    Option<&'static mut Sleeping>,
+    Option<&'static CompoundPart>,
+);
+
+/// Group balls into islands connected by this step's contacts and by bonds, and put
+/// an island to sleep once its mean kinetic energy has stayed low for long enough.
+pub fn island_sleep_system(
+    mut commands: Commands,
+    config: Res<SleepConfig>,
+    contacts: Res<Contacts>,
+    bonds: Bonds,
+    mut islands: ResMut<Islands>,
+    mut query: Query<IslandQueryData, With<Ball>>,
+) {
+    // Sleeping balls are represented by their island, so that a contact with any
+    // member of a sleeping island joins the whole island.
+    let mut nodes: Vec<Entity> = Vec::new();
+    let mut node_index: HashMap<Entity, usize> = HashMap::new();
+    let mut node_of: HashMap<Entity, usize> = HashMap::new();
+    for (entity, _, _, sleeping, _) in &query {
+        let node = sleeping.map_or(entity, |s| s.island);
+        let index = *node_index.entry(node).or_insert_with(|| {
+            nodes.push(node);
+            nodes.len() - 1
+        });
+        node_of.insert(entity, index);
+    }
+    let mut sets = UnionFind::new(nodes.len());
+
+    // Parts of a compound body are joined through the first of them
+    let mut first_part: HashMap<Entity, Entity> = HashMap::new();
+    let parts = query
+        .iter()
+        .filter_map(|(entity, .., part)| Some((entity, part?.body)))
+        .map(|(entity, body)| (*first_part.entry(body).or_insert(entity), entity));
+
+    for (a, b) in contacts
+        .pairs
+        .iter()
+        .copied()
+        .chain(bonds.pairs())
+        .chain(parts)
+    {
+        if let (Some(&node_a), Some(&node_b)) = (node_of.get(&a), node_of.get(&b)) {
+            sets.union(node_a, node_b);
+        }
+    }
+
+    // Total kinetic energy and number of awake balls per island
+    let mut energy: HashMap<Entity, (f32, u32)> = HashMap::new();
+    for (entity, velocity, mass, sleeping, _) in &query {
+        let root = nodes[sets.find(node_of[&entity])];
+        let island = energy.entry(root).or_insert((0.0, 0));
+        if sleeping.is_none() {
+            island.0 += 0.5 * mass.0 * velocity.length_squared();
+            island.1 += 1;
+        }
+    }
+
+    // Count consecutive low-energy steps per awake ball, and find the shortest
+    // count in each island
+    let mut min_steps: HashMap<Entity, u32> = HashMap::new();
+    for (entity, _, _, sleeping, _) in &query {
+        if sleeping.is_some() {
+            continue;
+        }
+        let root = nodes[sets.find(node_of[&entity])];
+        let (total, count) = energy[&root];
+        let steps = islands.low_energy_steps.entry(entity).or_insert(0);
+        if total / (count as f32) < config.energy_threshold {
+            *steps += 1;
+        } else {
+            *steps = 0;
+        }
+        let island_steps = min_steps.entry(root).or_insert(u32::MAX);
+        *island_steps = (*island_steps).min(*steps);
+    }
+
+    let mut num_sleeping = 0;
+    for (entity, mut velocity, _, sleeping, _) in &mut query {
+        let root = nodes[sets.find(node_of[&entity])];
+        let falls_asleep = min_steps
+            .get(&root)
+            .is_some_and(|&steps| steps >= config.steps);
+
+        match sleeping {
+            Some(mut sleeping) => {
+                // Relabel sleeping islands that have been joined to a newly sleeping one
+                if falls_asleep && sleeping.island != root {
+                    sleeping.island = root;
+                }
+                num_sleeping += 1;
+            }
+            None if falls_asleep => {
+                velocity.0 = Vec2::ZERO;
+                commands.entity(entity).insert(Sleeping { island: root });
+                islands.low_energy_steps.remove(&entity);
+                num_sleeping += 1;
+            }
+            None => (),
+        }
+    }
+
+    // Forget about balls that no longer exist
+    islands
+        .low_energy_steps
+        .retain(|entity, _| node_of.contains_key(entity));
+
+    islands.num_islands = energy.len();
+    islands.num_sleeping = num_sleeping;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint::DistanceJoint;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(SleepConfig {
+            steps: 3,
+            ..Default::default()
+        });
+        world.init_resource::<Islands>();
+        world.init_resource::<Contacts>();
+        world
+    }
+
+    fn ball(world: &mut World, velocity: Vec2) -> Entity {
+        world.spawn((Ball, Velocity(velocity), Mass(1.0))).id()
+    }
+
+    fn touching(world: &mut World, pairs: &[(Entity, Entity)]) {
+        world.resource_mut::<Contacts>().pairs = pairs.to_vec();
+    }
+
+    fn step(world: &mut World, steps: u32) {
+        for _ in 0..steps {
+            world.run_system_once(wake_system);
+            world.run_system_once(island_sleep_system);
+        }
+    }
+
+    fn island(world: &World, entity: Entity) -> Option<Entity> {
+        world
+            .get::<Sleeping>(entity)
+            .map(|sleeping| sleeping.island)
+    }
+
+    #[test]
+    fn resting_island_falls_asleep() {
+        let mut world = world();
+        let a = ball(&mut world, Vec2::new(0.5, 0.0));
+        let b = ball(&mut world, Vec2::ZERO);
+        let fast = ball(&mut world, Vec2::new(10.0, 0.0));
+        touching(&mut world, &[(a, b)]);
+
+        step(&mut world, 2);
+        assert!(island(&world, a).is_none());
+
+        step(&mut world, 1);
+        assert!(island(&world, a).is_some());
+        assert_eq!(island(&world, a), island(&world, b));
+        assert!(island(&world, fast).is_none());
+        assert_eq!(world.get::<Velocity>(a).unwrap().0, Vec2::ZERO);
+        assert_eq!(world.resource::<Islands>().num_sleeping, 2);
+    }
+
+    #[test]
+    fn island_wakes_on_impact() {
+        let mut world = world();
+        let a = ball(&mut world, Vec2::ZERO);
+        let b = ball(&mut world, Vec2::ZERO);
+        touching(&mut world, &[(a, b)]);
+        step(&mut world, 3);
+        assert!(island(&world, a).is_some() && island(&world, b).is_some());
+
+        // A nudge too small to wake it is forgotten
+        world.get_mut::<Velocity>(a).unwrap().0 = Vec2::new(1.0, 0.0);
+        world.run_system_once(wake_system);
+        assert!(island(&world, a).is_some());
+        assert_eq!(world.get::<Velocity>(a).unwrap().0, Vec2::ZERO);
+
+        // A real impact on one ball wakes the whole island
+        world.get_mut::<Velocity>(a).unwrap().0 = Vec2::new(50.0, 0.0);
+        world.run_system_once(wake_system);
+        assert!(island(&world, a).is_none());
+        assert!(island(&world, b).is_none());
+    }
+
+    #[test]
+    fn bonded_balls_share_an_island() {
+        let mut world = world();
+        let slow = ball(&mut world, Vec2::ZERO);
+        let fast = ball(&mut world, Vec2::new(10.0, 0.0));
+        world.spawn(DistanceJoint {
+            a: slow,
+            b: fast,
+            length: 10.0,
+        });
+
+        // Not touching, but the joint keeps the slow end awake with the fast one
+        step(&mut world, 5);
+        assert!(island(&world, slow).is_none());
+        assert_eq!(world.resource::<Islands>().num_islands, 1);
+
+        world.get_mut::<Velocity>(fast).unwrap().0 = Vec2::ZERO;
+        step(&mut world, 3);
+        assert!(island(&world, slow).is_some());
+        assert_eq!(island(&world, slow), island(&world, fast));
+    }
+}
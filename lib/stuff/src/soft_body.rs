@@ -0,0 +1,373 @@
+use crate::ball::{separation, Ball, Boundary, Mass, PhysicsSet, Velocity, WorldBounds};
+use crate::constraint::Spring;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::color::Color;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    default, Assets, Circle, ColorMaterial, Commands, Component, Entity, IntoSystemConfigs, Mesh,
+    Query, Res, Time, Transform, With,
+};
+use bevy::sprite::MaterialMesh2dBundle;
+use std::f32::consts::PI;
+
+/// The balls that make up a soft body.
+#[derive(Component)]
+pub struct SoftBody {
+    pub balls: Vec<Entity>,
+}
+
+/// Gas pressure inside a soft body, pushing its boundary outwards when it is
+/// squashed below its rest area and pulling it in when stretched.
+#[derive(Component)]
+pub struct Pressure {
+    /// Balls around the outside of the body, in order
+    pub boundary: Vec<Entity>,
+    pub rest_area: f32,
+    pub stiffness: f32,
+}
+
+pub enum SoftBodyShape {
+    /// Balls around a circle, each linked to its two neighbours either side
+    Ring { count: usize },
+    /// A square grid of balls with structural and shear springs
+    Lattice { rows: usize, columns: usize },
+}
+
+/// Builds a deformable body out of ordinary balls held together by springs.
+pub struct SoftBodyBuilder {
+    shape: SoftBodyShape,
+    ball_radius: f32,
+    ball_mass: f32,
+    stiffness: f32,
+    damping: f32,
+    pressure: Option<f32>,
+    color: Color,
+}
+
+impl SoftBodyBuilder {
+    /// A ring needs at least three balls, or its springs would link balls to
+    /// themselves
+    pub fn ring(count: usize) -> SoftBodyBuilder {
+        assert!(
+            count >= 3,
+            "A soft body ring needs at least 3 balls, not {}",
+            count
+        );
+        Self::new(SoftBodyShape::Ring { count })
+    }
+
+    pub fn lattice(rows: usize, columns: usize) -> SoftBodyBuilder {
+        assert!(
+            rows > 0 && columns > 0,
+            "A soft body lattice needs at least one ball, not {} x {}",
+            rows,
+            columns
+        );
+        Self::new(SoftBodyShape::Lattice { rows, columns })
+    }
+
+    fn new(shape: SoftBodyShape) -> SoftBodyBuilder {
+        SoftBodyBuilder {
+            shape,
+            ball_radius: 5.0,
+            ball_mass: 1.0,
+            stiffness: 200.0,
+            damping: 2.0,
+            pressure: None,
+            color: Color::WHITE,
+        }
+    }
+
+    /// Set the size and mass of each ball in the body
+    pub fn ball(self, radius: f32, mass: f32) -> SoftBodyBuilder {
+        SoftBodyBuilder {
+            ball_radius: radius,
+            ball_mass: mass,
+            ..self
+        }
+    }
+
+    /// Set the stiffness and damping of the internal springs
+    pub fn springs(self, stiffness: f32, damping: f32) -> SoftBodyBuilder {
+        SoftBodyBuilder {
+            stiffness,
+            damping,
+            ..self
+        }
+    }
+
+    /// Fill the body with gas to preserve its area
+    pub fn pressure(self, stiffness: f32) -> SoftBodyBuilder {
+        SoftBodyBuilder {
+            pressure: Some(stiffness),
+            ..self
+        }
+    }
+
+    pub fn color(self, color: Color) -> SoftBodyBuilder {
+        SoftBodyBuilder { color, ..self }
+    }
+
+    /// Distance from the centre of the body to the far side of its outermost ball
+    pub fn bounding_radius(&self) -> f32 {
+        let spacing = 2.0 * self.ball_radius;
+        let reach = match self.shape {
+            SoftBodyShape::Ring { count } => spacing / (2.0 * (PI / count as f32).sin()),
+            SoftBodyShape::Lattice { rows, columns } => {
+                spacing * Vec2::new(columns as f32 - 1.0, rows as f32 - 1.0).length() / 2.0
+            }
+        };
+        reach + self.ball_radius
+    }
+
+    /// Spawn the balls, springs and body entity, centred on `centre` and all moving
+    /// with `velocity`. Returns the body entity.
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+        centre: Vec2,
+        velocity: Vec2,
+    ) -> Entity {
+        let spacing = 2.0 * self.ball_radius;
+
+        // Positions relative to the centre, plus spring links by index, plus the
+        // boundary in counter-clockwise order
+        let mut positions = Vec::new();
+        let mut links = Vec::new();
+        let boundary: Vec<usize> = match self.shape {
+            SoftBodyShape::Ring { count } => {
+                // Neighbouring balls just touch
+                let radius = spacing / (2.0 * (PI / count as f32).sin());
+                for i in 0..count {
+                    let angle = 2.0 * PI * i as f32 / count as f32;
+                    positions.push(radius * Vec2::from_angle(angle));
+                }
+                for i in 0..count {
+                    links.push((i, (i + 1) % count));
+                    // Resist bending as well as stretching. A triangle is rigid
+                    // already, and in a square each spring across would be added
+                    // twice.
+                    if count > 4 || (count == 4 && i < 2) {
+                        links.push((i, (i + 2) % count));
+                    }
+                }
+                (0..count).collect()
+            }
+            SoftBodyShape::Lattice { rows, columns } => {
+                let offset = spacing * Vec2::new(columns as f32 - 1.0, rows as f32 - 1.0) / 2.0;
+                for row in 0..rows {
+                    for column in 0..columns {
+                        positions.push(spacing * Vec2::new(column as f32, row as f32) - offset);
+                    }
+                }
+                let index = |row: usize, column: usize| row * columns + column;
+                for row in 0..rows {
+                    for column in 0..columns {
+                        if column + 1 < columns {
+                            links.push((index(row, column), index(row, column + 1)));
+                        }
+                        if row + 1 < rows {
+                            links.push((index(row, column), index(row + 1, column)));
+                        }
+                        if row + 1 < rows && column + 1 < columns {
+                            links.push((index(row, column), index(row + 1, column + 1)));
+                            links.push((index(row, column + 1), index(row + 1, column)));
+                        }
+                    }
+                }
+                // Bottom edge, right edge, top edge, left edge
+                let mut boundary = Vec::new();
+                boundary.extend((0..columns).map(|column| index(0, column)));
+                boundary.extend((1..rows).map(|row| index(row, columns - 1)));
+                if rows > 1 {
+                    boundary.extend((0..columns - 1).rev().map(|column| index(rows - 1, column)));
+                }
+                if columns > 1 {
+                    boundary.extend((1..rows - 1).rev().map(|row| index(row, 0)));
+                }
+                boundary
+            }
+        };
+
+        let mesh = meshes.add(Circle::default());
+        let material = materials.add(self.color);
+
+        let balls: Vec<Entity> = positions
+            .iter()
+            .map(|position| {
+                commands
+                    .spawn((
+                        MaterialMesh2dBundle {
+                            mesh: mesh.clone().into(),
+                            material: material.clone(),
+                            transform: Transform::from_translation(
+                                (centre + *position).extend(0.0),
+                            )
+                            .with_scale(Vec2::splat(spacing).extend(1.0)),
+                            ..default()
+                        },
+                        Ball,
+                        Velocity(velocity),
+                        Mass(self.ball_mass),
+                    ))
+                    .id()
+            })
+            .collect();
+
+        for (a, b) in links {
+            commands.spawn(Spring {
+                a: balls[a],
+                b: balls[b],
+                rest_length: positions[a].distance(positions[b]),
+                stiffness: self.stiffness,
+                damping: self.damping,
+            });
+        }
+
+        let mut body = commands.spawn(SoftBody {
+            balls: balls.clone(),
+        });
+
+        if let Some(stiffness) = self.pressure {
+            let outline: Vec<Vec2> = boundary.iter().map(|&i| positions[i]).collect();
+            body.insert(Pressure {
+                boundary: boundary.iter().map(|&i| balls[i]).collect(),
+                rest_area: signed_area(&outline),
+                stiffness,
+            });
+        }
+
+        body.id()
+    }
+}
+
+/// Adds gas pressure to soft bodies. The springs are handled by `ConstraintPlugin`.
+#[derive(Default)]
+pub struct SoftBodyPlugin;
+
+impl Plugin for SoftBodyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, pressure_system.in_set(PhysicsSet::Constrain));
+    }
+}
+
+/// Shoelace formula, positive for counter-clockwise outlines
+fn signed_area(points: &[Vec2]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum::<f32>()
+        / 2.0
+}
+
+pub fn pressure_system(
+    bodies: Query<&Pressure>,
+    mut balls: Query<(&Transform, &mut Velocity, &Mass), With<Ball>>,
+    boundary: Res<Boundary>,
+    bounds: Res<WorldBounds>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    let box_size = boundary.periodic_box(&bounds);
+
+    for pressure in &bodies {
+        let Ok(outline) = pressure
+            .boundary
+            .iter()
+            .map(|&entity| balls.get(entity).map(|(t, _, _)| t.translation.truncate()))
+            .collect::<Result<Vec<Vec2>, _>>()
+        else {
+            continue;
+        };
+        // Put the outline back together where part of the body has wrapped
+        let outline: Vec<Vec2> = outline
+            .iter()
+            .map(|x| outline[0] + separation(*x, outline[0], box_size))
+            .collect();
+
+        let area = signed_area(&outline);
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+
+        // Relative to rest, so a body squashed to half its area pushes back with
+        // the same strength whatever its size
+        let p = pressure.stiffness * (pressure.rest_area / area - 1.0);
+
+        // Each edge is pushed along its outward normal in proportion to its length,
+        // with the force shared between the two balls at its ends
+        let n = outline.len();
+        for i in 0..n {
+            let j = (i + 1) % n;
+            let edge = outline[j] - outline[i];
+            let force = 0.5 * p * Vec2::new(edge.y, -edge.x);
+
+            for entity in [pressure.boundary[i], pressure.boundary[j]] {
+                if let Ok((_, mut velocity, mass)) = balls.get_mut(entity) {
+                    velocity.0 += force * dt / mass.0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+    use std::time::Duration;
+
+    #[test]
+    fn pressure_at_rest_across_the_wrap() {
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(10));
+        world.insert_resource(time);
+        world.insert_resource(Boundary::Wrap);
+        world.insert_resource(WorldBounds::new(100.0, 100.0));
+
+        // A 10 x 10 square outline split by the left and right edges
+        let corners = [(45.0, -5.0), (-45.0, -5.0), (-45.0, 5.0), (45.0, 5.0)];
+        let boundary: Vec<Entity> = corners
+            .iter()
+            .map(|&(x, y)| {
+                world
+                    .spawn((
+                        Ball,
+                        Transform::from_xyz(x, y, 0.0),
+                        Velocity(Vec2::ZERO),
+                        Mass(1.0),
+                    ))
+                    .id()
+            })
+            .collect();
+        world.spawn(Pressure {
+            boundary: boundary.clone(),
+            rest_area: 100.0,
+            stiffness: 1000.0,
+        });
+
+        world.run_system_once(pressure_system);
+        for entity in boundary {
+            assert!(world.get::<Velocity>(entity).unwrap().length() < 1e-4);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_lattice() {
+        SoftBodyBuilder::lattice(0, 3);
+    }
+
+    #[test]
+    fn bounding_radius() {
+        let ring = SoftBodyBuilder::ring(6).ball(1.0, 1.0);
+        // Six touching balls of radius 1 sit on a circle of radius 2
+        assert!((ring.bounding_radius() - 3.0).abs() < 1e-5);
+        let lattice = SoftBodyBuilder::lattice(2, 2).ball(1.0, 1.0);
+        assert!((lattice.bounding_radius() - (2.0f32.sqrt() + 1.0)).abs() < 1e-5);
+    }
+}
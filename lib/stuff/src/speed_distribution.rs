@@ -0,0 +1,297 @@
+use crate::ball::{Ball, Mass, Velocity, WorldBounds};
+use crate::compound::CompoundPart;
+use bevy::app::{App, AppExit, FixedLast, Last, Plugin, Update};
+use bevy::color::Color;
+use bevy::gizmos::GizmoPlugin;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, EventReader, Gizmos, IntoSystemConfigs, Local, Query, Res, ResMut, Resource,
+    With, Without,
+};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Resource)]
+pub struct SpeedHistogramConfig {
+    /// Sample the speeds every this many fixed frames
+    pub every: u32,
+    pub num_bins: usize,
+    /// Where to write the final histogram as CSV; printed to stdout if not given
+    pub output: Option<PathBuf>,
+}
+
+impl SpeedHistogramConfig {
+    pub fn new(every: u32) -> SpeedHistogramConfig {
+        SpeedHistogramConfig {
+            every,
+            num_bins: 30,
+            output: None,
+        }
+    }
+}
+
+/// The most recent sample of ball speeds, and the Maxwell-Boltzmann distribution
+/// fitted to it.
+#[derive(Resource, Default)]
+pub struct SpeedHistogram {
+    pub bin_width: f32,
+    pub counts: Vec<usize>,
+    /// Counts predicted by the fitted distribution
+    pub expected: Vec<f32>,
+    /// Fitted temperature (kT)
+    pub temperature: f32,
+    /// Chi-squared per degree of freedom of the fit; near one for a good fit
+    pub reduced_chi_squared: f32,
+    pub num_samples: usize,
+}
+
+/// Bins ball speeds, fits a 2D Maxwell-Boltzmann distribution, draws the histogram
+/// over the simulation and writes it out on exit.
+#[derive(Default)]
+pub struct SpeedDistributionPlugin;
+
+impl Plugin for SpeedDistributionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpeedHistogram>()
+            .add_systems(
+                FixedLast,
+                speed_histogram_system.run_if(resource_exists::<SpeedHistogramConfig>),
+            )
+            .add_systems(
+                Last,
+                report_speed_histogram_system.run_if(resource_exists::<SpeedHistogramConfig>),
+            );
+
+        // Nothing to draw with when headless
+        if app.is_plugin_added::<GizmoPlugin>() {
+            app.add_systems(
+                Update,
+                draw_speed_histogram_system.run_if(resource_exists::<SpeedHistogramConfig>),
+            );
+        }
+    }
+}
+
+/// Parts of compound bodies move with their body, so leave them out.
+type SpeedFilter = (With<Ball>, Without<CompoundPart>);
+
+pub fn speed_histogram_system(
+    query: Query<(&Velocity, &Mass), SpeedFilter>,
+    config: Res<SpeedHistogramConfig>,
+    mut histogram: ResMut<SpeedHistogram>,
+    mut frames: Local<u32>,
+) {
+    *frames += 1;
+    if *frames < config.every {
+        return;
+    }
+    *frames = 0;
+
+    let balls: Vec<(f32, f32)> = query.iter().map(|(v, m)| (v.length(), m.0)).collect();
+    if balls.is_empty() {
+        return;
+    }
+    let n = balls.len() as f32;
+
+    // With f(v) = (m v / kT) exp(-m v^2 / 2kT) for each ball, the maximum likelihood
+    // temperature is the mean kinetic energy per ball
+    let temperature = balls.iter().map(|(v, m)| 0.5 * m * v * v).sum::<f32>() / n;
+    if temperature <= 0.0 {
+        return;
+    }
+
+    // Cover the bulk of the distribution without a few fast balls squashing it
+    let rms_speed = (balls.iter().map(|(v, _)| v * v).sum::<f32>() / n).sqrt();
+    let max_speed = balls
+        .iter()
+        .map(|(v, _)| *v)
+        .fold(0.0, f32::max)
+        .min(3.0 * rms_speed);
+    let bin_width = (max_speed / config.num_bins as f32).max(f32::EPSILON);
+
+    let mut counts = vec![0; config.num_bins];
+    for (v, _) in &balls {
+        let bin = ((v / bin_width) as usize).min(config.num_bins - 1);
+        counts[bin] += 1;
+    }
+
+    // Expected counts: each ball contributes the probability its own (mass-dependent)
+    // distribution puts in the bin, from the CDF 1 - exp(-m v^2 / 2kT)
+    let cdf = |v: f32, m: f32| 1.0 - (-m * v * v / (2.0 * temperature)).exp();
+    let expected: Vec<f32> = (0..config.num_bins)
+        .map(|i| {
+            let low = i as f32 * bin_width;
+            let high = if i + 1 == config.num_bins {
+                f32::INFINITY
+            } else {
+                low + bin_width
+            };
+            balls
+                .iter()
+                .map(|(_, m)| cdf(high, *m) - cdf(low, *m))
+                .sum()
+        })
+        .collect();
+
+    // Pearson's chi-squared over bins with enough expected counts to be meaningful
+    let (chi_squared, num_bins_used) = counts
+        .iter()
+        .zip(&expected)
+        .filter(|(_, e)| **e >= 5.0)
+        .fold((0.0, 0), |(chi, used), (o, e)| {
+            (chi + (*o as f32 - e).powi(2) / e, used + 1)
+        });
+    // One degree of freedom lost to the total count and one to the fitted temperature
+    let degrees_of_freedom = (num_bins_used as f32 - 2.0).max(1.0);
+
+    *histogram = SpeedHistogram {
+        bin_width,
+        counts,
+        expected,
+        temperature,
+        reduced_chi_squared: chi_squared / degrees_of_freedom,
+        num_samples: histogram.num_samples + 1,
+    };
+}
+
+const PANEL_SIZE: Vec2 = Vec2::new(300.0, 150.0);
+const PANEL_MARGIN: f32 = 20.0;
+
+/// Draw the histogram and fitted curve in the bottom left corner of the world.
+pub fn draw_speed_histogram_system(
+    histogram: Res<SpeedHistogram>,
+    bounds: Res<WorldBounds>,
+    mut gizmos: Gizmos,
+) {
+    if histogram.counts.is_empty() {
+        return;
+    }
+    let origin = -bounds.half_size() + Vec2::splat(PANEL_MARGIN);
+
+    let num_bins = histogram.counts.len();
+    let tallest = histogram
+        .counts
+        .iter()
+        .map(|c| *c as f32)
+        .chain(histogram.expected.iter().copied())
+        .fold(1.0, f32::max);
+    let bar_width = PANEL_SIZE.x / num_bins as f32;
+    let scale = PANEL_SIZE.y / tallest;
+
+    gizmos.rect_2d(
+        origin + PANEL_SIZE / 2.0,
+        0.0,
+        PANEL_SIZE,
+        Color::srgb(0.5, 0.5, 0.5),
+    );
+
+    for (i, count) in histogram.counts.iter().enumerate() {
+        let height = *count as f32 * scale;
+        if height > 0.0 {
+            gizmos.rect_2d(
+                origin + Vec2::new((i as f32 + 0.5) * bar_width, height / 2.0),
+                0.0,
+                Vec2::new(bar_width, height),
+                Color::srgb(0.2, 0.8, 0.2),
+            );
+        }
+    }
+
+    gizmos.linestrip_2d(
+        histogram
+            .expected
+            .iter()
+            .enumerate()
+            .map(|(i, e)| origin + Vec2::new((i as f32 + 0.5) * bar_width, e * scale)),
+        Color::srgb(1.0, 1.0, 0.0),
+    );
+}
+
+/// Write the last histogram when the app exits.
+pub fn report_speed_histogram_system(
+    mut exit: EventReader<AppExit>,
+    config: Res<SpeedHistogramConfig>,
+    histogram: Res<SpeedHistogram>,
+) {
+    if exit.read().next().is_none() || histogram.counts.is_empty() {
+        return;
+    }
+
+    println!(
+        "Speed distribution: fitted kT {:.2}, reduced chi-squared {:.2} ({} samples)",
+        histogram.temperature, histogram.reduced_chi_squared, histogram.num_samples
+    );
+
+    let mut csv = String::from("speed_low,speed_high,count,expected\n");
+    for (i, (count, expected)) in histogram.counts.iter().zip(&histogram.expected).enumerate() {
+        csv += &format!(
+            "{},{},{},{:.2}\n",
+            i as f32 * histogram.bin_width,
+            (i + 1) as f32 * histogram.bin_width,
+            count,
+            expected
+        );
+    }
+
+    match &config.output {
+        Some(path) => {
+            if let Err(error) =
+                std::fs::File::create(path).and_then(|mut file| file.write_all(csv.as_bytes()))
+            {
+                eprintln!("Failed to write {}: {}", path.display(), error);
+            }
+        }
+        None => print!("{}", csv),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+
+    /// Histogram of one sample of balls with the given masses and velocities
+    fn histogram(balls: impl Iterator<Item = (f32, Vec2)>) -> SpeedHistogram {
+        let mut world = World::new();
+        world.insert_resource(SpeedHistogramConfig::new(1));
+        world.init_resource::<SpeedHistogram>();
+        for (mass, velocity) in balls {
+            world.spawn((Ball, Mass(mass), Velocity(velocity)));
+        }
+        world.run_system_once(speed_histogram_system);
+        world.remove_resource::<SpeedHistogram>().unwrap()
+    }
+
+    /// Balls of mass 1 and 2 whose speeds are evenly spaced quantiles of the 2D
+    /// Maxwell-Boltzmann distribution at `temperature`
+    fn maxwell_boltzmann(n: usize, temperature: f32) -> impl Iterator<Item = (f32, Vec2)> {
+        (0..n).map(move |i| {
+            let mass = (1 + i % 2) as f32;
+            let quantile = (i as f32 + 0.5) / n as f32;
+            // Inverse of the CDF 1 - exp(-m v^2 / 2kT)
+            let speed = (-2.0 * temperature / mass * (1.0 - quantile).ln()).sqrt();
+            (mass, speed * Vec2::from_angle(i as f32))
+        })
+    }
+
+    #[test]
+    fn fits_maxwell_boltzmann() {
+        let histogram = histogram(maxwell_boltzmann(2000, 50.0));
+        assert_eq!(histogram.counts.iter().sum::<usize>(), 2000);
+        assert!((histogram.temperature - 50.0).abs() < 1.0);
+        assert!(histogram.reduced_chi_squared < 2.0);
+
+        let expected: f32 = histogram.expected.iter().sum();
+        assert!((expected - 2000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn rejects_a_single_speed() {
+        // Same kinetic energy per ball as above, but nothing like the distribution
+        let balls = (0..2000).map(|i| (1.0, 10.0 * Vec2::from_angle(i as f32)));
+        let histogram = histogram(balls);
+        assert!((histogram.temperature - 50.0).abs() < 1e-3);
+        assert!(histogram.reduced_chi_squared > 100.0);
+    }
+}
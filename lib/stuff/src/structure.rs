@@ -0,0 +1,389 @@
+use crate::ball::{separation, Ball, Boundary, PhysicsSet, SortedBallsCache, WorldBounds};
+use bevy::app::{App, AppExit, FixedUpdate, Last, Plugin};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, EventReader, IntoSystemConfigs, Local, Query, Res, ResMut, Resource,
+    Transform, With,
+};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Resource)]
+pub struct StructureConfig {
+    /// Sample the balls every this many fixed frames
+    pub every: u32,
+    /// Number of samples in the sliding average
+    pub window: usize,
+    /// Largest separation for g(r), in mean ball diameters. Periodic boxes also limit
+    /// it to half the box.
+    pub range: f32,
+    pub num_bins: usize,
+    /// Compute S(k) for wave vectors up to this many multiples of 2 pi / L in each
+    /// direction
+    pub max_mode: Option<i32>,
+    /// Where to write g(r) and S(k) as CSV at the end of the run
+    pub rdf_output: Option<PathBuf>,
+    pub structure_factor_output: Option<PathBuf>,
+}
+
+impl StructureConfig {
+    pub fn new(every: u32) -> StructureConfig {
+        StructureConfig {
+            every,
+            window: 10,
+            range: 5.0,
+            num_bins: 100,
+            max_mode: None,
+            rdf_output: None,
+            structure_factor_output: None,
+        }
+    }
+}
+
+/// One sample's histograms, already normalised.
+struct StructureSample {
+    g: Vec<f32>,
+    s: Vec<f32>,
+}
+
+/// Pair correlation function g(r) and static structure factor S(k), averaged over
+/// the last few samples.
+#[derive(Resource, Default)]
+pub struct Structure {
+    pub r_bin_width: f32,
+    pub g: Vec<f32>,
+    pub k_bin_width: f32,
+    /// Radially averaged S(k); empty unless `max_mode` is set
+    pub s: Vec<f32>,
+    history: VecDeque<StructureSample>,
+}
+
+impl Structure {
+    /// Separation and height of the first peak of g(r): the first local maximum that
+    /// rises above the ideal gas value of one, so the empty core doesn't count. A
+    /// liquid's first peak sharpens as it crystallises or jams, and further peaks split.
+    pub fn first_peak(&self) -> Option<(f32, f32)> {
+        (0..self.g.len())
+            .find(|&i| self.g[i] > 1.0 && self.g.get(i + 1).is_none_or(|next| *next <= self.g[i]))
+            .map(|i| ((i as f32 + 0.5) * self.r_bin_width, self.g[i]))
+    }
+
+    /// Wave number and height of the highest peak of S(k)
+    pub fn structure_factor_peak(&self) -> Option<(f32, f32)> {
+        self.s
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, s)| ((i as f32 + 0.5) * self.k_bin_width, *s))
+    }
+}
+
+/// Measures the pair correlation function and, optionally, the static structure
+/// factor, to spot crystallisation and jamming in dense runs.
+///
+/// The pair search reuses the sorted order of `SortedBallsCache`, which
+/// `add_ball_physics_systems` keeps up to date whenever this is on.
+#[derive(Default)]
+pub struct StructurePlugin;
+
+impl Plugin for StructurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Structure>()
+            .register_diagnostic(Diagnostic::new(Self::RDF_PEAK))
+            .register_diagnostic(Diagnostic::new(Self::STRUCTURE_FACTOR_PEAK))
+            .add_systems(
+                FixedUpdate,
+                structure_system
+                    .in_set(PhysicsSet::PostCollide)
+                    .run_if(resource_exists::<StructureConfig>),
+            )
+            .add_systems(
+                Last,
+                report_structure_system.run_if(resource_exists::<StructureConfig>),
+            );
+    }
+}
+
+impl StructurePlugin {
+    /// Height of the first peak of g(r)
+    pub const RDF_PEAK: DiagnosticPath = DiagnosticPath::const_new("structure/rdf_peak");
+    /// Height of the highest peak of S(k)
+    pub const STRUCTURE_FACTOR_PEAK: DiagnosticPath =
+        DiagnosticPath::const_new("structure/structure_factor_peak");
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn structure_system(
+    mut diagnostics: Diagnostics,
+    balls: Query<&Transform, With<Ball>>,
+    cache: Res<SortedBallsCache>,
+    config: Res<StructureConfig>,
+    boundary: Res<Boundary>,
+    mut structure: ResMut<Structure>,
+    bounds: Res<WorldBounds>,
+    mut frames: Local<u32>,
+) {
+    *frames += 1;
+    if *frames < config.every {
+        return;
+    }
+    *frames = 0;
+
+    let n = balls.iter().len();
+    if n < 2 {
+        return;
+    }
+
+    let box_size = bounds.size;
+    let periodic_box = boundary.periodic_box(&bounds);
+
+    let diameter = balls.iter().map(|t| t.scale.x).sum::<f32>() / n as f32;
+    let mut r_max = config.range * diameter;
+    if periodic_box.is_some() {
+        r_max = r_max.min(box_size.min_element() / 2.0);
+    }
+    let r_bin_width = r_max / config.num_bins as f32;
+
+    // Count pairs by separation, to the nearest image if the box wraps
+    let mut counts = vec![0usize; config.num_bins];
+    cache.for_each_candidate_pair_periodic(r_max, periodic_box.map(|size| size.x), |a, b| {
+        if let Ok([ta, tb]) = balls.get_many([a, b]) {
+            let d = separation(
+                tb.translation.truncate(),
+                ta.translation.truncate(),
+                periodic_box,
+            );
+            let r = d.length();
+            if r < r_max {
+                // Rounding can put r just under r_max into the bin past the end
+                let bin = ((r / r_bin_width) as usize).min(config.num_bins - 1);
+                counts[bin] += 1;
+            }
+        }
+    });
+
+    // Normalise by the number of pairs an ideal gas would put in each annulus
+    let area = box_size.x * box_size.y;
+    let pair_density = 0.5 * (n * (n - 1)) as f32 / area;
+    let g = counts
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let (inner, outer) = (i as f32 * r_bin_width, (i + 1) as f32 * r_bin_width);
+            *c as f32 / (pair_density * PI * (outer * outer - inner * inner))
+        })
+        .collect();
+
+    // S(k) = |sum_j exp(i k . x_j)|^2 / N over the wave vectors that fit the box,
+    // binned by |k|
+    let k_bin_width = 2.0 * PI / box_size.min_element();
+    let s = match config.max_mode {
+        Some(max_mode) => {
+            let positions: Vec<Vec2> = balls.iter().map(|t| t.translation.truncate()).collect();
+            structure_factor(&positions, box_size, max_mode, k_bin_width)
+        }
+        None => Vec::new(),
+    };
+
+    let structure = &mut *structure;
+    if structure.r_bin_width != r_bin_width || structure.k_bin_width != k_bin_width {
+        // The bins have moved, e.g. the balls have grown, so start again
+        structure.history.clear();
+    }
+    structure.r_bin_width = r_bin_width;
+    structure.k_bin_width = k_bin_width;
+    structure.history.push_back(StructureSample { g, s });
+    while structure.history.len() > config.window {
+        structure.history.pop_front();
+    }
+
+    let average = |histograms: Vec<&Vec<f32>>| -> Vec<f32> {
+        let len = histograms.iter().map(|h| h.len()).min().unwrap_or(0);
+        (0..len)
+            .map(|i| histograms.iter().map(|h| h[i]).sum::<f32>() / histograms.len() as f32)
+            .collect()
+    };
+    structure.g = average(structure.history.iter().map(|s| &s.g).collect());
+    structure.s = average(structure.history.iter().map(|s| &s.s).collect());
+
+    if let Some((_, peak)) = structure.first_peak() {
+        diagnostics.add_measurement(&StructurePlugin::RDF_PEAK, || peak as f64);
+    }
+    if let Some((_, peak)) = structure.structure_factor_peak() {
+        diagnostics.add_measurement(&StructurePlugin::STRUCTURE_FACTOR_PEAK, || peak as f64);
+    }
+}
+
+/// Radially averaged static structure factor of points in a periodic box, for wave
+/// vectors `2 pi (nx / Lx, ny / Ly)` with `|nx|, |ny| <= max_mode`.
+fn structure_factor(
+    positions: &[Vec2],
+    box_size: Vec2,
+    max_mode: i32,
+    k_bin_width: f32,
+) -> Vec<f32> {
+    let two_pi = 2.0 * PI;
+    let k_max = two_pi * max_mode as f32 / box_size.max_element();
+    let num_bins = (k_max / k_bin_width).ceil() as usize + 1;
+    let mut sums = vec![0.0; num_bins];
+    let mut counts = vec![0usize; num_bins];
+
+    // k and -k give the same S, so take half the plane
+    for nx in 0..=max_mode {
+        for ny in -max_mode..=max_mode {
+            if nx == 0 && ny <= 0 {
+                continue;
+            }
+            let k = two_pi * Vec2::new(nx as f32 / box_size.x, ny as f32 / box_size.y);
+            let bin = (k.length() / k_bin_width) as usize;
+            if bin >= num_bins {
+                continue;
+            }
+            let (re, im) = positions.iter().fold((0.0, 0.0), |(re, im), x| {
+                let phase = k.dot(*x);
+                (re + phase.cos(), im + phase.sin())
+            });
+            sums[bin] += (re * re + im * im) / positions.len() as f32;
+            counts[bin] += 1;
+        }
+    }
+
+    sums.iter()
+        .zip(counts)
+        .map(|(s, c)| if c > 0 { s / c as f32 } else { 0.0 })
+        .collect()
+}
+
+/// Print the peaks of g(r) and S(k), and write them out, when the app exits.
+pub fn report_structure_system(
+    mut exit: EventReader<AppExit>,
+    config: Res<StructureConfig>,
+    structure: Res<Structure>,
+) {
+    if exit.read().next().is_none() || structure.g.is_empty() {
+        return;
+    }
+
+    if let Some((r, g)) = structure.first_peak() {
+        println!("g(r) first peak: {:.2} at r = {:.2}", g, r);
+    }
+    if let Some((k, s)) = structure.structure_factor_peak() {
+        println!("S(k) peak: {:.2} at k = {:.4}", s, k);
+    }
+
+    let write = |path: &Path, header: &str, bin_width: f32, values: &[f32]| {
+        let mut csv = String::from(header);
+        for (i, value) in values.iter().enumerate() {
+            csv += &format!("{},{:.4}\n", (i as f32 + 0.5) * bin_width, value);
+        }
+        if let Err(error) =
+            std::fs::File::create(path).and_then(|mut file| file.write_all(csv.as_bytes()))
+        {
+            eprintln!("Failed to write {}: {}", path.display(), error);
+        }
+    };
+    if let Some(path) = &config.rdf_output {
+        write(path, "r,g\n", structure.r_bin_width, &structure.g);
+    }
+    if let Some(path) = &config.structure_factor_output {
+        write(path, "k,s\n", structure.k_bin_width, &structure.s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ball::update_sorted_balls_cache;
+    use bevy::diagnostic::DiagnosticsStore;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::World;
+
+    /// g(r) from one sample of `positions` in a 100 x 100 box, balls of diameter 5
+    fn measured_g(positions: &[Vec2], boundary: Boundary) -> Vec<f32> {
+        let mut world = World::new();
+        world.insert_resource(StructureConfig::new(1));
+        world.insert_resource(boundary);
+        world.insert_resource(WorldBounds {
+            size: Vec2::splat(100.0),
+        });
+        world.init_resource::<Structure>();
+        world.init_resource::<SortedBallsCache>();
+        world.init_resource::<DiagnosticsStore>();
+        for x in positions {
+            world.spawn((
+                Ball,
+                Transform::from_translation(x.extend(0.0)).with_scale(Vec2::splat(5.0).extend(1.0)),
+            ));
+        }
+        world.run_system_once(update_sorted_balls_cache);
+        world.run_system_once(structure_system);
+        world.resource::<Structure>().g.clone()
+    }
+
+    #[test]
+    fn pairs_are_counted_around_the_wrap() {
+        // Scattered with a small linear congruential generator
+        let mut state = 7u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let positions: Vec<Vec2> = (0..60).map(|_| 100.0 * Vec2::new(next(), next())).collect();
+
+        // Every pair, by brute force. The range is 5 diameters, well inside half the box.
+        let r_bin_width = 25.0 / 100.0;
+        let mut expected = vec![0.0; 100];
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                let r = separation(*b, *a, Some(Vec2::splat(100.0))).length();
+                if r < 25.0 {
+                    expected[(r / r_bin_width) as usize] += 1.0;
+                }
+            }
+        }
+        let pair_density = 0.5 * 60.0 * 59.0 / 10000.0;
+        for (i, count) in expected.iter_mut().enumerate() {
+            let (inner, outer) = (i as f32 * r_bin_width, (i + 1) as f32 * r_bin_width);
+            *count /= pair_density * PI * (outer * outer - inner * inner);
+        }
+
+        let g = measured_g(&positions, Boundary::Wrap);
+        for (g, expected) in g.iter().zip(&expected) {
+            assert!((g - expected).abs() < 1e-3, "{} != {}", g, expected);
+        }
+        // Without the wrap, pairs straddling an edge are missed
+        assert_ne!(measured_g(&positions, Boundary::Reflect), g);
+    }
+
+    fn structure(g: Vec<f32>) -> Structure {
+        Structure {
+            r_bin_width: 2.0,
+            g,
+            ..Structure::default()
+        }
+    }
+
+    #[test]
+    fn first_peak_is_not_the_highest() {
+        // Noise in the core, the first shell, then a taller split second shell
+        let s = structure(vec![0.0, 0.1, 0.0, 1.8, 1.8, 0.9, 1.2, 2.5, 1.0]);
+        assert_eq!(s.first_peak(), Some((7.0, 1.8)));
+    }
+
+    #[test]
+    fn no_peak_in_an_ideal_gas() {
+        assert_eq!(structure(vec![0.0, 0.5, 1.0, 1.0]).first_peak(), None);
+        assert_eq!(structure(Vec::new()).first_peak(), None);
+    }
+
+    #[test]
+    fn peak_in_the_last_bin() {
+        assert_eq!(
+            structure(vec![0.0, 0.5, 1.5]).first_peak(),
+            Some((5.0, 1.5))
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::benchmark::BenchmarkReport;
+use crate::cli::{Broadphase, Cli};
+use clap::{Arg, CommandFactory, ValueEnum};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The combinations to benchmark.
+#[derive(Debug, Clone)]
+pub struct SweepConfig {
+    pub balls: Vec<usize>,
+    pub broadphases: Vec<Broadphase>,
+    pub rates: Vec<f64>,
+    pub frames: f64,
+    pub seed: u64,
+    pub output: Option<PathBuf>,
+    /// Options passed on to every benchmark, such as the world size or contact
+    /// model; see `forwarded_args`
+    pub args: Vec<String>,
+}
+
+/// One benchmarked combination.
+pub struct SweepResult {
+    pub num_balls: usize,
+    pub broadphase: Broadphase,
+    pub rate: f64,
+    /// `None` if the benchmark failed
+    pub report: Option<BenchmarkReport>,
+}
+
+/// Global options that each benchmark is given its own value for
+const OVERRIDDEN: [&str; 4] = ["broadphase", "physics_rate", "seed", "headless"];
+
+/// The sweep's own command line `args` (without the program name) with the `sweep`
+/// subcommand and its options taken out, along with the options each benchmark is
+/// given its own value for, leaving everything to pass on to the benchmarks.
+pub fn forwarded_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let cli = Cli::command();
+    let sweep = cli.find_subcommand("sweep").expect("sweep is a subcommand");
+    let matches = |arg: &Arg, name: &str| match name.strip_prefix("--") {
+        Some(long) => arg.get_long() == Some(long),
+        None => name.chars().nth(1) == arg.get_short(),
+    };
+
+    let mut forwarded = Vec::new();
+    let mut in_sweep = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if arg == "sweep" && !in_sweep {
+                in_sweep = true;
+            } else {
+                forwarded.push(arg);
+            }
+            continue;
+        }
+
+        // Values are either attached, as in --seed=1 or -s1, or the next argument
+        let (name, attached) = match arg.split_once('=') {
+            Some((name, _)) => (name, true),
+            None if !arg.starts_with("--") => (&arg[..arg.len().min(2)], arg.len() > 2),
+            None => (arg.as_str(), false),
+        };
+
+        // The bin's own ball count is varied by the sweep
+        if name == "--num-balls" || name == "-n" {
+            if !attached {
+                args.next();
+            }
+            continue;
+        }
+
+        let sweep_option = in_sweep
+            .then(|| sweep.get_arguments().find(|a| matches(a, name)))
+            .flatten();
+        let global_option = cli.get_arguments().find(|a| matches(a, name));
+        let takes_value = sweep_option
+            .or(global_option)
+            .is_some_and(|a| a.get_action().takes_values());
+        let value = if takes_value && !attached {
+            args.next()
+        } else {
+            None
+        };
+
+        let overridden = sweep_option.is_none()
+            && global_option.is_some_and(|a| OVERRIDDEN.contains(&a.get_id().as_str()));
+        if sweep_option.is_none() && !overridden {
+            forwarded.push(arg);
+            forwarded.extend(value);
+        }
+    }
+    forwarded
+}
+
+fn broadphase_name(broadphase: Broadphase) -> String {
+    broadphase
+        .to_possible_value()
+        .map_or(String::new(), |value| value.get_name().to_string())
+}
+
+/// Benchmark every combination in turn, each in a fresh copy of the current binary so
+/// that they don't share a window, caches or warm-up, then print a table comparing
+/// them and write it as CSV.
+pub fn run_sweep(config: &SweepConfig) -> Vec<SweepResult> {
+    let exe = std::env::current_exe().expect("Can't find the current executable");
+    let combinations: Vec<(usize, Broadphase, f64)> = config
+        .balls
+        .iter()
+        .flat_map(|balls| {
+            config.broadphases.iter().flat_map(move |broadphase| {
+                config
+                    .rates
+                    .iter()
+                    .map(move |rate| (*balls, *broadphase, *rate))
+            })
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for (i, (num_balls, broadphase, rate)) in combinations.iter().copied().enumerate() {
+        println!(
+            "[{}/{}] {} balls, {}, {} Hz",
+            i + 1,
+            combinations.len(),
+            num_balls,
+            broadphase_name(broadphase),
+            rate
+        );
+
+        let report_path =
+            std::env::temp_dir().join(format!("sweep-{}-{}.csv", std::process::id(), i));
+        let status = Command::new(&exe)
+            .args(&config.args)
+            .arg("--headless")
+            .arg("--num-balls")
+            .arg(num_balls.to_string())
+            .arg("--broadphase")
+            .arg(broadphase_name(broadphase))
+            .arg("--physics-rate")
+            .arg(rate.to_string())
+            .arg("--seed")
+            .arg(config.seed.to_string())
+            .arg("benchmark")
+            .arg("--frames")
+            .arg(config.frames.to_string())
+            .arg("--report")
+            .arg(&report_path)
+            .stdout(Stdio::null())
+            .status();
+
+        let report = match status {
+            Ok(status) if status.success() => std::fs::read_to_string(&report_path)
+                .ok()
+                .and_then(|csv| csv.lines().nth(1).and_then(BenchmarkReport::from_csv_row)),
+            Ok(status) => {
+                eprintln!("Benchmark failed: {}", status);
+                None
+            }
+            Err(error) => {
+                eprintln!("Failed to run {}: {}", exe.display(), error);
+                None
+            }
+        };
+        let _ = std::fs::remove_file(&report_path);
+
+        results.push(SweepResult {
+            num_balls,
+            broadphase,
+            rate,
+            report,
+        });
+    }
+
+    print_table(&results);
+    if let Some(path) = &config.output {
+        if let Err(error) = write_csv(path, &results) {
+            eprintln!("Failed to write {}: {}", path.display(), error);
+        }
+    }
+    results
+}
+
+/// Per-phase times are in milliseconds per fixed frame, split between broadphase and
+/// narrowphase the same way for every broadphase (see `PhaseTimings`).
+fn print_table(results: &[SweepResult]) {
+    println!(
+        "{:>8} {:>10} {:>8} {:>10} {:>8} {:>10} {:>9} {:>9} {:>9} {:>9}",
+        "balls",
+        "broadphase",
+        "rate",
+        "fixed fps",
+        "fps",
+        "collisions",
+        "integrate",
+        "broad",
+        "narrow",
+        "warp"
+    );
+    for result in results {
+        let Some(report) = &result.report else {
+            println!(
+                "{:>8} {:>10} {:>8} {:>10}",
+                result.num_balls,
+                broadphase_name(result.broadphase),
+                result.rate,
+                "failed"
+            );
+            continue;
+        };
+        let per_frame = |seconds: f64| 1000.0 * seconds / report.fixed_frames.max(1) as f64;
+        println!(
+            "{:>8} {:>10} {:>8} {:>10.1} {:>8.1} {:>10} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+            result.num_balls,
+            broadphase_name(result.broadphase),
+            result.rate,
+            report.fixed_fps(),
+            report.fps(),
+            report.collisions,
+            per_frame(report.integrate),
+            per_frame(report.broadphase),
+            per_frame(report.narrowphase),
+            per_frame(report.warp),
+        );
+    }
+}
+
+fn write_csv(path: &Path, results: &[SweepResult]) -> std::io::Result<()> {
+    let mut csv = format!("balls,broadphase,rate,{}\n", BenchmarkReport::CSV_HEADER);
+    for result in results {
+        csv += &format!(
+            "{},{},{},{}\n",
+            result.num_balls,
+            broadphase_name(result.broadphase),
+            result.rate,
+            result
+                .report
+                .as_ref()
+                .map_or(String::new(), BenchmarkReport::to_csv_row)
+        );
+    }
+    std::fs::File::create(path)?.write_all(csv.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(args: &str) -> Vec<String> {
+        forwarded_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn globals_are_passed_on() {
+        assert_eq!(
+            forwarded(
+                "--num-balls 100 --world-width 800 --gravity=-50 sweep --balls 10,20 \
+                 --contact-model hertzian --walls -f 30 --rdf-output sweep"
+            ),
+            [
+                "--world-width",
+                "800",
+                "--gravity=-50",
+                "--contact-model",
+                "hertzian",
+                "--walls",
+                "--rdf-output",
+                "sweep"
+            ]
+        );
+    }
+
+    #[test]
+    fn overridden_options_are_dropped() {
+        assert!(forwarded(
+            "-n 5 --broadphase naive -p 60 --seed=3 -s4 --headless sweep --rates 64,128 \
+             --broadphases naive,sap -o table.csv --frames=100"
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::ball::{Ball, Mass, PhysicsSet, Velocity};
+use crate::brownian::Brownian;
+use crate::compound::CompoundPart;
+use crate::random::{random_float, random_normal};
+use crate::sleep::Sleeping;
+use bevy::app::{App, FixedUpdate, Plugin};
+use bevy::math::Vec2;
+use bevy::prelude::{
+    resource_exists, IntoSystemConfigs, Query, Res, ResMut, Resource, Time, With, Without,
+};
+use bevy_prng::ChaCha8Rng;
+use bevy_rand::prelude::GlobalEntropy;
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ThermostatKind {
+    /// Scale every velocity to hit the target temperature exactly, every step
+    Rescale,
+    /// Scale velocities to relax exponentially towards the target temperature
+    Berendsen,
+    /// Stochastic collisions with a heat bath: balls occasionally get a fresh velocity
+    /// drawn from the Maxwell-Boltzmann distribution
+    Andersen,
+    /// Friction plus random kicks, balanced so the bath is at the target temperature
+    Langevin,
+}
+
+/// Holds the balls at a constant temperature.
+///
+/// Temperatures are in units where Boltzmann's constant is one, so in 2D the mean
+/// kinetic energy per ball is `temperature`.
+#[derive(Resource)]
+pub struct Thermostat {
+    pub kind: ThermostatKind,
+    pub temperature: f32,
+    /// Coupling time constant: the Berendsen relaxation time, the mean time between
+    /// Andersen collisions for each ball, or the inverse Langevin friction.
+    /// Unused by `ThermostatKind::Rescale`.
+    pub time_constant: f32,
+}
+
+impl Thermostat {
+    pub fn new(kind: ThermostatKind, temperature: f32) -> Thermostat {
+        Thermostat {
+            kind,
+            temperature,
+            time_constant: 1.0,
+        }
+    }
+}
+
+/// Applies the `Thermostat` after each collision step.
+#[derive(Default)]
+pub struct ThermostatPlugin;
+
+impl Plugin for ThermostatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            thermostat_system
+                .in_set(PhysicsSet::PostCollide)
+                .run_if(resource_exists::<Thermostat>),
+        );
+    }
+}
+
+/// Instantaneous temperature of a set of balls: mean kinetic energy per ball, which
+/// in 2D is kT.
+pub fn temperature<'a>(balls: impl Iterator<Item = (&'a Velocity, &'a Mass)>) -> f32 {
+    let (energy, count) = balls.fold((0.0, 0), |(energy, count), (velocity, mass)| {
+        (energy + 0.5 * mass.0 * velocity.length_squared(), count + 1)
+    });
+    if count > 0 {
+        energy / count as f32
+    } else {
+        0.0
+    }
+}
+
+/// Brownian bodies already have their own heat bath
+type ThermostatFilter = (
+    With<Ball>,
+    Without<CompoundPart>,
+    Without<Sleeping>,
+    Without<Brownian>,
+);
+
+pub fn thermostat_system(
+    mut query: Query<(&mut Velocity, &Mass), ThermostatFilter>,
+    thermostat: Res<Thermostat>,
+    mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    let target = thermostat.temperature;
+
+    match thermostat.kind {
+        ThermostatKind::Rescale | ThermostatKind::Berendsen => {
+            let current = temperature(query.iter());
+            if current <= 0.0 {
+                return;
+            }
+            let ratio = target / current;
+            let lambda = match thermostat.kind {
+                ThermostatKind::Rescale => ratio.sqrt(),
+                // https://en.wikipedia.org/wiki/Berendsen_thermostat
+                _ => (1.0 + dt / thermostat.time_constant * (ratio - 1.0))
+                    .max(0.0)
+                    .sqrt(),
+            };
+            for (mut velocity, _) in &mut query {
+                velocity.0 *= lambda;
+            }
+        }
+        ThermostatKind::Andersen => {
+            let probability = dt / thermostat.time_constant;
+            for (mut velocity, mass) in &mut query {
+                if random_float(&mut rng) < probability {
+                    velocity.0 = maxwell_boltzmann_velocity(&mut rng, target, mass.0);
+                }
+            }
+        }
+        ThermostatKind::Langevin => {
+            // Exact solution of the Ornstein-Uhlenbeck process over one step, so the
+            // friction can't overshoot however short the time constant
+            let decay = (-dt / thermostat.time_constant).exp();
+            let noise = (1.0 - decay * decay).sqrt();
+            for (mut velocity, mass) in &mut query {
+                velocity.0 = decay * velocity.0
+                    + noise * maxwell_boltzmann_velocity(&mut rng, target, mass.0);
+            }
+        }
+    }
+}
+
+/// Random velocity from the 2D Maxwell-Boltzmann distribution: each component is
+/// normal with variance kT / m.
+pub fn maxwell_boltzmann_velocity(
+    rng: &mut ResMut<GlobalEntropy<ChaCha8Rng>>,
+    temperature: f32,
+    mass: f32,
+) -> Vec2 {
+    let sigma = (temperature / mass).sqrt();
+    sigma * Vec2::new(random_normal(rng), random_normal(rng))
+}